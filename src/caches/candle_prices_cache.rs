@@ -1,49 +1,2122 @@
-use std::{collections::{BTreeMap}};
-use chrono::{DateTime, Utc};
-use crate::models::{candle_type::CandleType, candle_data::CandleData};
+use std::{borrow::Cow, collections::{BTreeMap}, ops::Bound};
+use chrono::{DateTime, Duration, FixedOffset, TimeZone, Timelike, Utc};
+use crate::models::{candle_type::CandleType, candle_data::CandleData, candle_data_formats::{LightweightChartCandle, LightweightChartValue}, candle_stats::CandleStats, trading_session::TradingSessions, market_calendar::MarketCalendar};
+use crate::caches::candle_prices_view::CandlePricesView;
+#[cfg(feature = "prost")]
+use crate::models::candle_proto::CandlePricesProto;
+use crate::utils::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+/// Candles taken on each side of a candle to get the median close in `detect_spikes`
+const SPIKE_NEIGHBORS: usize = 2;
+
+/// Most decimal places `to_delta_bytes` tries to scale prices and volumes to integers with
+const MAX_DELTA_DECIMALS: i32 = 9;
+
+/// Largest magnitude below which every integer is exactly representable by f64
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_992.0;
+
+/// Approximate per-entry overhead of `BTreeMap` nodes: parent pointer, lengths and spare slots
+const BTREE_ENTRY_OVERHEAD: usize = 16;
+
+/// Defines how `init` treats a candle already present at the same date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InitPolicy {
+    /// Init candle always replaces the existing one
+    OverwriteAlways,
+    /// Candle with the most recent `datetime` wins
+    KeepNewer,
+    /// Candles are combined: open from the older, close from the newer,
+    /// high/low extremes and the bigger volume (both cover the same interval)
+    Merge,
+}
+
+/// Defines where a new candle takes its open price from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Open is the first tick of the interval, so gaps between candles are kept
+    FirstTick,
+    /// Open is the previous candle close, so candles are continuous
+    PreviousClose,
+}
+
+/// Defines how tick volumes are combined into the candle volume
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VolumeMode {
+    /// Volumes of ticks are summed, for feeds sending traded size
+    Sum,
+    /// Volume of the latest tick is kept, for feeds sending cumulative volume
+    Last,
+    /// The biggest tick volume is kept
+    Max,
+}
+
+/// Date range with explicit endpoint semantics, e.g. [from, to] or (from, to)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub from: Bound<DateTime<Utc>>,
+    pub to: Bound<DateTime<Utc>>,
+}
 
 #[derive(Debug, Clone)]
 pub struct CandlePricesCache{
     pub candle_type: CandleType,
-    pub prices_by_date: BTreeMap<i64, CandleData>
+    pub prices_by_date: BTreeMap<i64, CandleData>,
+    pub init_policy: InitPolicy,
+    pub open_mode: OpenMode,
+    pub volume_mode: VolumeMode,
+    /// Retention window: ticks for candles older than `now - window` are ignored
+    pub window: Option<Duration>,
+    /// Session windows used when `candle_type` is `Session`: ticks out of sessions are skipped
+    pub sessions: Option<TradingSessions>,
+    /// Day and Month candles start at local midnight of the offset instead of UTC midnight
+    pub day_offset: Option<FixedOffset>,
 }
 
 impl CandlePricesCache {
     pub fn new(candle_type: CandleType) -> Self{
-        Self { candle_type, prices_by_date: BTreeMap::new() }
+        Self { candle_type, prices_by_date: BTreeMap::new(), init_policy: InitPolicy::OverwriteAlways, open_mode: OpenMode::FirstTick, volume_mode: VolumeMode::Sum, window: None, sessions: None, day_offset: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.prices_by_date.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prices_by_date.is_empty()
+    }
+
+    /// Removes candles with start date before `cutoff` and returns them in date order
+    pub fn extract_before(&mut self, cutoff: DateTime<Utc>) -> Vec<CandleData> {
+        let kept = self.prices_by_date.split_off(&cutoff.timestamp());
+
+        std::mem::replace(&mut self.prices_by_date, kept).into_values().collect()
+    }
+
+    /// Approximates heap memory of candles: per candle the key, `CandleData` and an average
+    /// `BTreeMap` node overhead. Struct itself and session settings aren't counted
+    pub fn estimated_bytes(&self) -> usize {
+        self.prices_by_date.len()
+            * (std::mem::size_of::<i64>() + std::mem::size_of::<CandleData>() + BTREE_ENTRY_OVERHEAD)
+    }
+
+    /// Gets candle start date for the candle type, None if date is out of trading sessions
+    fn get_start_date(&self, candle_type: &CandleType, datetime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        match (candle_type, self.sessions.as_ref(), self.day_offset) {
+            (CandleType::Session, Some(sessions), _) => sessions.get_start_date(datetime),
+            (_, _, Some(offset)) => Some(candle_type.get_start_date_at_offset(datetime, offset)),
+            _ => Some(candle_type.get_start_date(datetime)),
+        }
+    }
+
+    /// Gets end of the candle starting at specified date
+    fn get_end_date(&self, candle_date: DateTime<Utc>) -> DateTime<Utc> {
+        match (&self.candle_type, self.sessions.as_ref(), self.day_offset) {
+            (CandleType::Session, Some(sessions), _) => match sessions.get_duration(candle_date) {
+                Some(duration) => candle_date + duration,
+                None => self.candle_type.get_end_date(candle_date),
+            },
+            (_, _, Some(offset)) => self.candle_type.get_end_date_at_offset(candle_date, offset),
+            _ => self.candle_type.get_end_date(candle_date),
+        }
+    }
+
+    /// Gets start of the first candle containing or following specified date
+    fn get_first_candle_date(&self, datetime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.get_start_date(&self.candle_type, datetime).or_else(|| match (&self.candle_type, self.sessions.as_ref()) {
+            (CandleType::Session, Some(sessions)) => sessions.get_next_start_date(datetime),
+            _ => None,
+        })
+    }
+
+    /// Iterates candle start dates of [date_from, date_to) in ascending order, starting from the candle
+    /// containing `date_from`. Respects trading sessions and day offset, unlike `CandleType::iter_start_dates`
+    fn iter_candle_dates(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        std::iter::successors(self.get_first_candle_date(date_from), move |last_date| {
+            self.get_first_candle_date(self.get_end_date(*last_date))
+                .filter(|next_date| next_date > last_date)
+        })
+        .take_while(move |date| *date < date_to)
     }
 
     pub fn init(&mut self, candle: CandleData){
-        self.prices_by_date.insert(candle.datetime.timestamp(), candle);
+        let Some(candle_date) = self.get_start_date(&self.candle_type, candle.datetime) else {
+            return;
+        };
+        let timestamp_sec = candle_date.timestamp();
+        let Some(existing) = self.prices_by_date.get_mut(&timestamp_sec) else {
+            self.prices_by_date.insert(timestamp_sec, candle);
+            return;
+        };
+
+        match self.init_policy {
+            InitPolicy::OverwriteAlways => *existing = candle,
+            InitPolicy::KeepNewer => {
+                if candle.datetime >= existing.datetime {
+                    *existing = candle;
+                }
+            }
+            InitPolicy::Merge => {
+                let (older, newer) = if candle.datetime < existing.datetime {
+                    (&candle, &*existing)
+                } else {
+                    (&*existing, &candle)
+                };
+                let merged = CandleData {
+                    open: older.open,
+                    open_datetime: older.open_datetime,
+                    close: newer.close,
+                    high: f64::max(older.high, newer.high),
+                    low: f64::min(older.low, newer.low),
+                    datetime: newer.datetime,
+                    volume: f64::max(older.volume, newer.volume),
+                    tick_count: u64::max(older.tick_count, newer.tick_count),
+                };
+                *existing = merged;
+            }
+        }
+    }
+
+    /// Inserts only candles whose date isn't cached yet. Returns inserted count
+    pub fn fill_missing(&mut self, candles: &[CandleData]) -> usize {
+        let mut inserted_count = 0;
+
+        for candle in candles {
+            let Some(candle_date) = self.get_start_date(&self.candle_type, candle.datetime) else {
+                continue;
+            };
+            let timestamp_sec = candle_date.timestamp();
+
+            if let std::collections::btree_map::Entry::Vacant(entry) = self.prices_by_date.entry(timestamp_sec) {
+                entry.insert(candle.clone());
+                inserted_count += 1;
+            }
+        }
+
+        inserted_count
+    }
+
+    /// Replaces the candle of its date with the authoritative one regardless of init policy.
+    /// Returns false if the same candle is already cached, so repeated upserts change nothing
+    pub fn upsert_authoritative(&mut self, candle: CandleData) -> bool {
+        let Some(candle_date) = self.get_start_date(&self.candle_type, candle.datetime) else {
+            return false;
+        };
+        let timestamp_sec = candle_date.timestamp();
+
+        if self.prices_by_date.get(&timestamp_sec) == Some(&candle) {
+            return false;
+        }
+
+        self.prices_by_date.insert(timestamp_sec, candle);
+
+        true
     }
 
-    pub fn update(&mut self, datetime: DateTime<Utc>, rate: f64, volume: f64){
-        let candle_date = self.candle_type.get_start_date(datetime);
+    /// Applies tick to its candle. Returns false if tick is older than the retention window
+    /// or out of trading sessions
+    pub fn update(&mut self, datetime: DateTime<Utc>, rate: f64, volume: f64) -> bool {
+        let Some(candle_date) = self.get_start_date(&self.candle_type, datetime) else {
+            return false;
+        };
+
+        if let Some(window) = self.window {
+            if candle_date < Utc::now() - window {
+                return false;
+            }
+        }
+
         let timestamp_sec = candle_date.timestamp();
         let target_candle = self.prices_by_date.get_mut(&timestamp_sec);
 
         match target_candle {
-            Some(candle) => candle.update(datetime, rate, volume),
+            Some(candle) => {
+                let previous_volume = candle.volume;
+                candle.update(datetime, rate, volume);
+
+                match self.volume_mode {
+                    VolumeMode::Sum => {}
+                    VolumeMode::Last => candle.volume = volume,
+                    VolumeMode::Max => candle.volume = f64::max(previous_volume, volume),
+                }
+            }
             None => {
-                let candle_model = CandleData::new(candle_date, rate, volume);
+                let mut candle_model = CandleData::new(datetime, rate, volume);
+
+                if self.open_mode == OpenMode::PreviousClose {
+                    if let Some((_date, previous)) = self.prices_by_date.range(..timestamp_sec).next_back() {
+                        candle_model.open = previous.close;
+                        candle_model.high = f64::max(candle_model.high, previous.close);
+                        candle_model.low = f64::min(candle_model.low, previous.close);
+                    }
+                }
+
                 self.prices_by_date.insert(timestamp_sec, candle_model);
             },
         }
+
+        true
     }
 
     pub fn get_by_date_range(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<CandleData>{
-        let mut result = Vec::new();
+        self.iter_by_date_range(date_from, date_to).cloned().collect()
+    }
+
+    /// Serializes candles of the date range to TradingView lightweight-charts array of
+    /// `{ time, open, high, low, close }`, time being unix seconds of the candle start
+    pub fn to_lightweight_chart_json(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> String {
+        let candles: Vec<LightweightChartCandle> = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .map(|(timestamp, candle)| LightweightChartCandle::new(*timestamp, candle))
+            .collect();
+
+        serde_json::to_string(&candles).expect("candles serialize to json")
+    }
+
+    /// Serializes volumes of the date range to lightweight-charts histogram array of `{ time, value }`,
+    /// matching candles of `to_lightweight_chart_json`
+    pub fn to_lightweight_chart_volume_json(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> String {
+        let volumes: Vec<LightweightChartValue> = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .map(|(timestamp, candle)| LightweightChartValue { time: *timestamp, value: candle.volume })
+            .collect();
+
+        serde_json::to_string(&volumes).expect("volumes serialize to json")
+    }
+
+    /// Gets candles of the date range preceded by up to `context_before` candles before it.
+    /// Returns the candles and index of the first candle of the requested range
+    pub fn get_by_date_range_with_context(
+        &self,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+        context_before: usize,
+    ) -> (Vec<CandleData>, usize) {
+        let mut candles: Vec<CandleData> = self
+            .prices_by_date
+            .range(..date_from.timestamp())
+            .rev()
+            .take(context_before)
+            .map(|(_date, candle)| candle.clone())
+            .collect();
+        candles.reverse();
+
+        let range_start = candles.len();
+        candles.extend(self.iter_by_date_range(date_from, date_to).cloned());
+
+        (candles, range_start)
+    }
+
+    /// Gets candles with start date in [date_from, date_to], so a candle starting exactly at `date_to` is included
+    pub fn get_by_date_range_inclusive(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<CandleData> {
+        if date_from > date_to {
+            return Vec::new();
+        }
+
+        self.prices_by_date
+            .range(date_from.timestamp()..=date_to.timestamp())
+            .map(|(_date, candle)| candle.clone())
+            .collect()
+    }
+
+    /// Gets candles of the date range without cloning: items borrow the cache, so it can't be
+    /// mutated while they live. Call `into_owned` on the items that must outlive the borrow
+    pub fn get_by_date_range_cow(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<Cow<'_, CandleData>> {
+        self.iter_by_date_range(date_from, date_to)
+            .map(Cow::Borrowed)
+            .collect()
+    }
+
+    /// Gets candles whose date fits the bounds. Returns empty vec for an inverted range
+    pub fn get_by_bounds(&self, bounds: Bounds) -> Vec<CandleData> {
+        let from = bounds.from.map(|date| date.timestamp());
+        let to = bounds.to.map(|date| date.timestamp());
+
+        let is_valid = match (from, to) {
+            (Bound::Excluded(from), Bound::Excluded(to)) => from < to,
+            (Bound::Included(from) | Bound::Excluded(from), Bound::Included(to) | Bound::Excluded(to)) => from <= to,
+            _ => true,
+        };
+
+        if !is_valid {
+            return Vec::new();
+        }
+
+        self.prices_by_date
+            .range((from, to))
+            .map(|(_date, candle)| candle.clone())
+            .collect()
+    }
+
+    /// Gets up to `limit` first candles of the date range, visiting only the returned ones
+    pub fn get_by_date_range_capped(
+        &self,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+        limit: usize,
+    ) -> Vec<CandleData> {
+        take_capped(self.iter_by_date_range(date_from, date_to), limit)
+    }
+
+    /// Iterates candles of [date_from, date_to) in date order, borrowing them from the cache
+    pub fn iter_by_date_range(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> impl Iterator<Item = &CandleData> {
+        self.prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .map(|(_date, candle)| candle)
+    }
+
+    /// Gets (timestamp in seconds, candle) pairs of the date range as they are stored
+    pub fn entries_in_range(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<(i64, CandleData)> {
+        self.prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .map(|(date, candle)| (*date, candle.clone()))
+            .collect()
+    }
+
+    /// Gets a slot per interval start of the date range, None where the candle is missing
+    pub fn get_dense(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<Option<CandleData>> {
+        self.iter_candle_dates(date_from, date_to)
+            .map(|date| self.prices_by_date.get(&date.timestamp()).cloned())
+            .collect()
+    }
+
+    /// Gets candles of the date range starting within regular trading hours of the calendar
+    pub fn get_rth(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>, calendar: &MarketCalendar) -> Vec<CandleData> {
+        self.prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .filter(|(date, _candle)| {
+                Utc.timestamp_opt(**date, 0)
+                    .single()
+                    .is_some_and(|date| calendar.is_regular_hours(date))
+            })
+            .map(|(_date, candle)| candle.clone())
+            .collect()
+    }
+
+    /// Gets a candle per interval of the date range: missing ones are flat candles at the previous close
+    /// with zero volume. Leading missing intervals are skipped until the first real candle
+    pub fn get_by_date_range_filled(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<CandleData> {
+        let mut result: Vec<CandleData> = Vec::new();
+
+        for date in self.iter_candle_dates(date_from, date_to) {
+            if let Some(candle) = self.prices_by_date.get(&date.timestamp()) {
+                result.push(candle.clone());
+            } else if let Some(previous) = result.last() {
+                result.push(CandleData::flat(date, previous.close));
+            }
+        }
+
+        result
+    }
+
+    /// Gets candles from `date_from` up to the candle containing `now`. If the earliest candles are missing,
+    /// they are flat candles at the last close before `date_from`, if any. If the latest candles are missing,
+    /// the series is extended with flat candles at the last close. Flat candles have zero volume
+    pub fn get_by_date_range_to_now(&self, date_from: DateTime<Utc>, now: DateTime<Utc>) -> Vec<CandleData> {
+        let date_to = match self.get_start_date(&self.candle_type, now) {
+            Some(candle_date) => self.get_end_date(candle_date),
+            None => now,
+        };
+        let first_date = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .next()
+            .map(|(date, _candle)| *date);
+        let mut candles = Vec::new();
+
+        if let Some((_date, previous)) = self.prices_by_date.range(..date_from.timestamp()).next_back() {
+            let leading_dates = self
+                .iter_candle_dates(date_from, date_to)
+                .skip_while(|date| *date < date_from)
+                .take_while(|date| first_date.is_none_or(|first_date| date.timestamp() < first_date));
+
+            for date in leading_dates {
+                candles.push(CandleData::flat(date, previous.close));
+            }
+        }
+
+        candles.extend(self.get_by_date_range(date_from, date_to));
+        let last = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .next_back()
+            .and_then(|(date, candle)| Some((Utc.timestamp_opt(*date, 0).single()?, candle.close)));
+
+        if let Some((last_date, last_close)) = last {
+            for date in self.iter_candle_dates(last_date, date_to).skip(1) {
+                candles.push(CandleData::flat(date, last_close));
+            }
+        }
+
+        candles
+    }
+
+    /// Gets candles of the date range with volume bigger or equals `min_volume`
+    pub fn get_by_date_range_min_volume(
+        &self,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+        min_volume: f64,
+    ) -> Vec<CandleData> {
+        self.prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .filter(|(_date, candle)| candle.volume >= min_volume)
+            .map(|(_date, candle)| candle.clone())
+            .collect()
+    }
+
+    /// Gets candles with start date not earlier than `now - duration`
+    pub fn get_recent(&self, duration: Duration, now: DateTime<Utc>) -> Vec<CandleData> {
+        self.prices_by_date
+            .range((now - duration).timestamp()..)
+            .map(|(_date, candle)| candle.clone())
+            .collect()
+    }
+
+    /// Gets candles with start date before `cutoff` and whether the last of them straddles it.
+    /// Straddling candle is returned as is: its OHLC reflects only ticks seen so far
+    pub fn snapshot_until(&self, cutoff: DateTime<Utc>) -> (Vec<CandleData>, bool) {
+        let candles: Vec<CandleData> = self
+            .prices_by_date
+            .range(..cutoff.timestamp())
+            .map(|(_date, candle)| candle.clone())
+            .collect();
+        let is_last_partial = self
+            .prices_by_date
+            .range(..cutoff.timestamp())
+            .next_back()
+            .and_then(|(date, _candle)| Utc.timestamp_opt(*date, 0).single())
+            .is_some_and(|date| self.get_end_date(date) > cutoff);
+
+        (candles, is_last_partial)
+    }
+
+    /// Gets up to `n` latest candles whose interval has fully elapsed before `now`, in date order.
+    /// The interval is taken from the candle start date, not from its last tick date
+    pub fn get_latest_n_closed(&self, n: usize, now: DateTime<Utc>) -> Vec<CandleData> {
+        let mut candles: Vec<CandleData> = self
+            .prices_by_date
+            .range(..=now.timestamp())
+            .rev()
+            .filter(|(date, _candle)| {
+                Utc.timestamp_opt(**date, 0)
+                    .single()
+                    .is_some_and(|candle_date| self.get_end_date(candle_date) <= now)
+            })
+            .take(n)
+            .map(|(_date, candle)| candle.clone())
+            .collect();
+
+        candles.reverse();
+
+        candles
+    }
+
+    /// Gets the candle right before the candle containing specified date
+    pub fn prev(&self, datetime: DateTime<Utc>) -> Option<CandleData> {
+        let timestamp_sec = self
+            .get_start_date(&self.candle_type, datetime)
+            .unwrap_or(datetime)
+            .timestamp();
+
+        self.prices_by_date
+            .range(..timestamp_sec)
+            .next_back()
+            .map(|(_date, candle)| candle.clone())
+    }
+
+    /// Gets the candle right after the candle containing specified date
+    pub fn next(&self, datetime: DateTime<Utc>) -> Option<CandleData> {
+        let timestamp_sec = self
+            .get_start_date(&self.candle_type, datetime)
+            .unwrap_or(datetime)
+            .timestamp();
+
+        self.prices_by_date
+            .range(timestamp_sec + 1..)
+            .next()
+            .map(|(_date, candle)| candle.clone())
+    }
+
+    /// Yields candles of the date range in time order as batches of up to `chunk` items.
+    /// Zero `chunk` yields nothing
+    pub fn iter_chunks(
+        &self,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+        chunk: usize,
+    ) -> impl Iterator<Item = Vec<CandleData>> + '_ {
+        let mut candles = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .map(|(_date, candle)| candle);
+
+        std::iter::from_fn(move || {
+            let batch: Vec<CandleData> = candles.by_ref().take(chunk).cloned().collect();
+
+            if batch.is_empty() {
+                None
+            } else {
+                Some(batch)
+            }
+        })
+    }
+
+    /// Gets (min low, max high) of candles in the date range without cloning them
+    pub fn price_extent(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Option<(f64, f64)> {
         let timestamp_from = date_from.timestamp();
         let timestamp_to = date_to.timestamp();
 
-        for (_date, candle) in self.prices_by_date.range(timestamp_from..timestamp_to){
-            result.push(candle.clone());
+        self.prices_by_date
+            .range(timestamp_from..timestamp_to)
+            .fold(None, |extent, (_date, candle)| match extent {
+                Some((low, high)) => Some((f64::min(low, candle.low), f64::max(high, candle.high))),
+                None => Some((candle.low, candle.high)),
+            })
+    }
+
+    /// Re-aggregates stored candles into buckets of the new candle type and switches to it.
+    /// Candles out of trading sessions are dropped when switching to `Session`
+    pub fn rebucket(&mut self, new_type: CandleType) {
+        let mut prices_by_date: BTreeMap<i64, CandleData> = BTreeMap::new();
+
+        for candle in std::mem::take(&mut self.prices_by_date).into_values() {
+            let Some(candle_date) = self.get_start_date(&new_type, candle.datetime) else {
+                continue;
+            };
+            let timestamp_sec = candle_date.timestamp();
+
+            match prices_by_date.get_mut(&timestamp_sec) {
+                Some(bucket) => bucket.merge(&candle),
+                None => {
+                    prices_by_date.insert(timestamp_sec, candle);
+                }
+            }
+        }
+
+        self.prices_by_date = prices_by_date;
+        self.candle_type = new_type;
+    }
+
+    /// Gets period and anchor in seconds of the candle type buckets if they have fixed length,
+    /// None for months and configured trading sessions
+    fn get_fixed_grid(&self, candle_type: &CandleType) -> Option<(i64, i64)> {
+        match (candle_type, self.sessions.as_ref()) {
+            (CandleType::Month, _) | (CandleType::Session, Some(_)) => None,
+            _ => {
+                let reference_date = DateTime::<Utc>::default();
+                let period = candle_type.get_duration(reference_date).num_seconds();
+                let anchor = self.get_start_date(candle_type, reference_date)?.timestamp();
+
+                Some((period, anchor.rem_euclid(period)))
+            }
+        }
+    }
+
+    /// Checks every cache candle lies within a single `target` candle, so `target` candles are unions
+    /// of cache candles. Respects trading sessions and day offset
+    pub(crate) fn nests_into(&self, target: &CandleType) -> bool {
+        let Some((period, anchor)) = self.get_fixed_grid(&self.candle_type) else {
+            return false;
+        };
+        let is_boundary = |seconds: i64| (seconds - anchor).rem_euclid(period) == 0;
+
+        match (target, self.sessions.as_ref()) {
+            (CandleType::Month, _) => match self.get_fixed_grid(&CandleType::Day) {
+                Some((day_period, day_anchor)) => day_period % period == 0 && is_boundary(day_anchor),
+                None => false,
+            },
+            (CandleType::Session, Some(sessions)) => {
+                86400 % period == 0
+                    && sessions.sessions.iter().all(|session| {
+                        is_boundary(session.start.num_seconds_from_midnight() as i64)
+                            && is_boundary(session.end.num_seconds_from_midnight() as i64)
+                    })
+            }
+            _ => match self.get_fixed_grid(target) {
+                Some((target_period, target_anchor)) => {
+                    target_period > period && target_period % period == 0 && is_boundary(target_anchor)
+                }
+                None => false,
+            },
+        }
+    }
+
+    /// Gets copy of the cache with candles aggregated into buckets of the coarser `target` type:
+    /// open of the first candle, close of the last, high/low extremes and summed volume.
+    /// None unless each `target` candle is a union of whole cache candles, e.g. weeks don't split into months
+    pub fn resample(&self, target: CandleType) -> Option<CandlePricesCache> {
+        if !self.nests_into(&target) {
+            return None;
+        }
+
+        let mut resampled = self.clone();
+        resampled.rebucket(target);
+
+        Some(resampled)
+    }
+
+    /// Gets statistics of the date range in a single pass, None for an empty range
+    pub fn stats(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Option<CandleStats> {
+        let mut stats: Option<CandleStats> = None;
+        let mut close_m2 = 0.0;
+
+        for (_date, candle) in self.prices_by_date.range(date_from.timestamp()..date_to.timestamp()) {
+            let Some(stats) = stats.as_mut() else {
+                stats = Some(CandleStats {
+                    count: 1,
+                    total_volume: candle.volume,
+                    average_close: candle.close,
+                    min: candle.low,
+                    max: candle.high,
+                    close_std_dev: 0.0,
+                });
+                continue;
+            };
+
+            stats.count += 1;
+            stats.total_volume += candle.volume;
+            stats.min = f64::min(stats.min, candle.low);
+            stats.max = f64::max(stats.max, candle.high);
+
+            let delta = candle.close - stats.average_close;
+            stats.average_close += delta / stats.count as f64;
+            close_m2 += delta * (candle.close - stats.average_close);
+        }
+
+        stats.map(|mut stats| {
+            stats.close_std_dev = (close_m2 / stats.count as f64).sqrt();
+            stats
+        })
+    }
+
+    /// Re-aggregates candles of the date range into `target` buckets phased from `anchor`
+    /// instead of UTC boundaries. Source candles must not be coarser than the phase shift
+    /// (e.g. minute candles for hours shifted by 30 minutes), otherwise a source candle spans
+    /// two buckets and is counted only in the one containing its start. Month target stays
+    /// calendar aligned since its length isn't fixed
+    pub fn get_realigned(
+        &self,
+        date_from: DateTime<Utc>,
+        date_to: DateTime<Utc>,
+        anchor: DateTime<Utc>,
+        target: CandleType,
+    ) -> Vec<CandleData> {
+        let mut buckets: BTreeMap<i64, CandleData> = BTreeMap::new();
+        let anchor_sec = anchor.timestamp();
+
+        for (date, candle) in self.prices_by_date.range(date_from.timestamp()..date_to.timestamp()) {
+            let bucket_sec = match target {
+                CandleType::Month => target.get_start_date(candle.datetime).timestamp(),
+                _ => {
+                    let duration_sec = target.get_duration(candle.datetime).num_seconds();
+
+                    anchor_sec + (date - anchor_sec).div_euclid(duration_sec) * duration_sec
+                }
+            };
+
+            match buckets.get_mut(&bucket_sec) {
+                Some(bucket) => bucket.merge(candle),
+                None => {
+                    buckets.insert(bucket_sec, candle.clone());
+                }
+            }
+        }
+
+        buckets.into_values().collect()
+    }
+
+    /// Gets running volume weighted typical price ((high + low + close) / 3) from `anchor` per candle
+    /// of [anchor, date_to). Candles are skipped while the cumulative volume is zero
+    pub fn anchored_vwap(&self, anchor: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<(DateTime<Utc>, f64)> {
+        let mut price_volume = 0.0;
+        let mut volume = 0.0;
+        let mut result = Vec::new();
+
+        for (date, candle) in self.prices_by_date.range(anchor.timestamp()..date_to.timestamp()) {
+            price_volume += (candle.high + candle.low + candle.close) / 3.0 * candle.volume;
+            volume += candle.volume;
+
+            if volume == 0.0 {
+                continue;
+            }
+
+            if let Some(date) = Utc.timestamp_opt(*date, 0).single() {
+                result.push((date, price_volume / volume));
+            }
         }
 
         result
     }
 
+    /// Gets stable FNV-1a hash of (timestamp, open, high, low, close, volume) of all candles in date order
+    pub fn digest(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const FNV_PRIME: u64 = 0x100000001b3;
+        let mut hash = FNV_OFFSET_BASIS;
+
+        for (date, candle) in self.prices_by_date.iter() {
+            let fields = [
+                *date as u64,
+                candle.open.to_bits(),
+                candle.high.to_bits(),
+                candle.low.to_bits(),
+                candle.close.to_bits(),
+                candle.volume.to_bits(),
+            ];
+
+            for byte in fields.iter().flat_map(|field| field.to_le_bytes()) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+
+        hash
+    }
+
+    /// Converts candles of the date range to protobuf message
+    #[cfg(feature = "prost")]
+    pub fn to_proto(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> CandlePricesProto {
+        CandlePricesProto {
+            candle_type: self.candle_type.clone().into(),
+            candles: self
+                .prices_by_date
+                .range(date_from.timestamp()..date_to.timestamp())
+                .map(|(_date, candle)| candle.into())
+                .collect(),
+        }
+    }
+
+    /// Creates cache from protobuf message, None if candle type is unknown or a date is out of range
+    #[cfg(feature = "prost")]
+    pub fn from_proto(proto: &CandlePricesProto) -> Option<Self> {
+        let candle_type = CandleType::try_from(proto.candle_type).ok()?;
+        let mut cache = Self::new(candle_type);
+
+        for candle in proto.candles.iter() {
+            cache.init(CandleData::try_from(candle).ok()?);
+        }
+
+        Some(cache)
+    }
+
+    /// Encodes candles of the date range compactly as varints: candle type, count, price and volume
+    /// decimals (0 if values aren't decimal, d + 1 otherwise), then per candle the zigzag timestamp delta,
+    /// open, close, high, low and volume, tick count and candle dates as zigzag nanos from start.
+    /// Prices and volume are written as zigzag deltas from the previous candle of integers scaled by
+    /// 10^decimals, the fewest decimals keeping every value exact, so 1.08123 -> 1.08125 takes one byte.
+    /// Without such decimals they fall back to f64 bits xor-ed with the previous candle ones
+    pub fn to_delta_bytes(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<u8> {
+        let candles: Vec<(&i64, &CandleData)> = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .collect();
+        let prices: Vec<f64> = candles
+            .iter()
+            .flat_map(|(_timestamp, candle)| [candle.open, candle.close, candle.high, candle.low])
+            .collect();
+        let volumes: Vec<f64> = candles.iter().map(|(_timestamp, candle)| candle.volume).collect();
+        let price_decimals = get_exact_decimals(&prices);
+        let volume_decimals = get_exact_decimals(&volumes);
+        let field_decimals = [price_decimals, price_decimals, price_decimals, price_decimals, volume_decimals];
+        let mut bytes = Vec::new();
+        let mut prev_timestamp = 0;
+        let mut prev_fields = [0u64; 5];
+
+        write_varint(&mut bytes, i32::from(self.candle_type.clone()) as u64);
+        write_varint(&mut bytes, candles.len() as u64);
+
+        for decimals in [price_decimals, volume_decimals] {
+            write_varint(&mut bytes, decimals.map_or(0, |decimals| decimals as u64 + 1));
+        }
+
+        for (timestamp, candle) in candles {
+            let values = [candle.open, candle.close, candle.high, candle.low, candle.volume];
+            let mut fields = [0u64; 5];
+
+            write_varint(&mut bytes, zigzag_encode(timestamp - prev_timestamp));
+
+            for (index, value) in values.into_iter().enumerate() {
+                let prev_field = prev_fields[index];
+
+                match field_decimals[index].and_then(|decimals| to_scaled(value, decimals)) {
+                    Some(scaled) => {
+                        write_varint(&mut bytes, zigzag_encode(scaled.wrapping_sub(prev_field as i64)));
+                        fields[index] = scaled as u64;
+                    }
+                    None => {
+                        write_varint(&mut bytes, value.to_bits() ^ prev_field);
+                        fields[index] = value.to_bits();
+                    }
+                }
+            }
+
+            write_varint(&mut bytes, candle.tick_count);
+
+            for date in [candle.datetime, candle.open_datetime] {
+                let nanos = (date.timestamp() - timestamp) * 1_000_000_000 + date.timestamp_subsec_nanos() as i64;
+                write_varint(&mut bytes, zigzag_encode(nanos));
+            }
+
+            prev_timestamp = *timestamp;
+            prev_fields = fields;
+        }
+
+        bytes
+    }
+
+    /// Decodes cache encoded by `to_delta_bytes`, None if bytes are malformed
+    pub fn from_delta_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut position = 0;
+        let candle_type = CandleType::try_from(read_varint(bytes, &mut position)? as i32).ok()?;
+        let count = read_varint(bytes, &mut position)?;
+        let mut header_decimals = [None; 2];
+
+        for decimals in header_decimals.iter_mut() {
+            *decimals = match read_varint(bytes, &mut position)? {
+                0 => None,
+                value if value <= MAX_DELTA_DECIMALS as u64 + 1 => Some(value as i32 - 1),
+                _ => return None,
+            };
+        }
+
+        let [price_decimals, volume_decimals] = header_decimals;
+        let field_decimals = [price_decimals, price_decimals, price_decimals, price_decimals, volume_decimals];
+        let mut cache = Self::new(candle_type);
+        let mut timestamp = 0i64;
+        let mut fields = [0u64; 5];
+        let mut values = [0f64; 5];
+
+        for _ in 0..count {
+            timestamp = timestamp.checked_add(zigzag_decode(read_varint(bytes, &mut position)?))?;
+
+            for (index, field) in fields.iter_mut().enumerate() {
+                let delta = read_varint(bytes, &mut position)?;
+
+                values[index] = match field_decimals[index] {
+                    Some(decimals) => {
+                        *field = (*field as i64).wrapping_add(zigzag_decode(delta)) as u64;
+                        from_scaled(*field as i64, decimals)
+                    }
+                    None => {
+                        *field ^= delta;
+                        f64::from_bits(*field)
+                    }
+                };
+            }
+
+            let tick_count = read_varint(bytes, &mut position)?;
+            let mut dates = [DateTime::<Utc>::default(); 2];
+
+            for date in dates.iter_mut() {
+                let nanos = zigzag_decode(read_varint(bytes, &mut position)?);
+                let seconds = timestamp.checked_add(nanos.div_euclid(1_000_000_000))?;
+                *date = Utc.timestamp_opt(seconds, nanos.rem_euclid(1_000_000_000) as u32).single()?;
+            }
+
+            let [open, close, high, low, volume] = values;
+            let [datetime, open_datetime] = dates;
+            cache.prices_by_date.insert(
+                timestamp,
+                CandleData { open, close, high, low, datetime, volume, tick_count, open_datetime },
+            );
+        }
+
+        if position != bytes.len() {
+            return None;
+        }
+
+        Some(cache)
+    }
+
+    /// Gets start dates of candles of the date range whose high or low deviates from the median close
+    /// of up to `SPIKE_NEIGHBORS` candles on each side by more than `threshold_pct` percents
+    pub fn detect_spikes(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>, threshold_pct: f64) -> Vec<DateTime<Utc>> {
+        let candles: Vec<(&i64, &CandleData)> = self
+            .prices_by_date
+            .range(date_from.timestamp()..date_to.timestamp())
+            .collect();
+        let mut spikes = Vec::new();
+
+        for (index, (date, candle)) in candles.iter().enumerate() {
+            let neighbors = index.saturating_sub(SPIKE_NEIGHBORS)..candles.len().min(index + SPIKE_NEIGHBORS + 1);
+            let mut closes: Vec<f64> = candles[neighbors]
+                .iter()
+                .filter(|(neighbor_date, _neighbor)| neighbor_date != date)
+                .map(|(_neighbor_date, neighbor)| neighbor.close)
+                .collect();
+
+            if closes.is_empty() {
+                continue;
+            }
+
+            closes.sort_by(f64::total_cmp);
+            let middle = closes.len() / 2;
+            let median = if closes.len().is_multiple_of(2) {
+                (closes[middle - 1] + closes[middle]) / 2.0
+            } else {
+                closes[middle]
+            };
+
+            if median == 0.0 {
+                continue;
+            }
+
+            let deviation_pct = f64::max((candle.high - median).abs(), (candle.low - median).abs()) / median.abs() * 100.0;
+
+            if deviation_pct > threshold_pct {
+                if let Some(date) = Utc.timestamp_opt(**date, 0).single() {
+                    spikes.push(date);
+                }
+            }
+        }
+
+        spikes
+    }
+
+    /// Gets read-only handle of the cache
+    pub fn view(&self) -> CandlePricesView<'_> {
+        CandlePricesView::new(self)
+    }
+
     pub fn clear(&mut self) {
         self.prices_by_date.clear()
     }
-}
\ No newline at end of file
+}
+
+/// Caches are equal when they hold the same candles of the same type, settings are not compared
+impl PartialEq for CandlePricesCache {
+    fn eq(&self, other: &Self) -> bool {
+        self.candle_type == other.candle_type && self.prices_by_date == other.prices_by_date
+    }
+}
+
+/// Clones up to `limit` first candles, pulling no more of them from the iterator
+pub(crate) fn take_capped<'a>(candles: impl Iterator<Item = &'a CandleData>, limit: usize) -> Vec<CandleData> {
+    candles.take(limit).cloned().collect()
+}
+
+/// Gets the fewest decimals scaling every value to an integer that converts back to the exact same value
+fn get_exact_decimals(values: &[f64]) -> Option<i32> {
+    (0..=MAX_DELTA_DECIMALS).find(|decimals| values.iter().all(|value| to_scaled(*value, *decimals).is_some()))
+}
+
+/// Scales value by 10^decimals, None unless the integer converts back to the exact same value
+fn to_scaled(value: f64, decimals: i32) -> Option<i64> {
+    let scaled = (value * 10f64.powi(decimals)).round();
+
+    if scaled.is_nan() || scaled.abs() > MAX_EXACT_INTEGER {
+        return None;
+    }
+
+    let scaled = scaled as i64;
+
+    (from_scaled(scaled, decimals).to_bits() == value.to_bits()).then_some(scaled)
+}
+
+fn from_scaled(scaled: i64, decimals: i32) -> f64 {
+    scaled as f64 / 10f64.powi(decimals)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::caches::candle_prices_cache::{take_capped, Bounds, CandlePricesCache, InitPolicy, OpenMode, VolumeMode};
+    use std::ops::Bound;
+    use crate::models::candle::BidAskCandle;
+    use crate::models::candle_data::CandleData;
+    use crate::models::candle_type::CandleType;
+    use crate::models::trading_session::{TradingSession, TradingSessions};
+    use crate::models::market_calendar::MarketCalendar;
+    use chrono::{DateTime, Duration, FixedOffset, NaiveTime, TimeZone, Utc, Weekday};
+
+    #[tokio::test]
+    async fn price_extent() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(from, 10.0, 1.0);
+        cache.update(from + Duration::seconds(10), 12.5, 1.0);
+        cache.update(from + Duration::minutes(1), 9.0, 1.0);
+        cache.update(from + Duration::minutes(2), 11.0, 1.0);
+        cache.update(from + Duration::minutes(3), 1.0, 1.0);
+
+        let extent = cache.price_extent(from, from + Duration::minutes(3));
+
+        assert_eq!(extent, Some((9.0, 12.5)));
+        assert_eq!(cache.price_extent(from + Duration::minutes(10), from + Duration::minutes(20)), None);
+    }
+
+    fn create_live_cache(init_policy: InitPolicy) -> (CandlePricesCache, DateTime<Utc>) {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        cache.init_policy = init_policy;
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(date + Duration::seconds(10), 10.0, 1.0);
+        cache.update(date + Duration::seconds(40), 11.0, 2.0);
+
+        (cache, date)
+    }
+
+    #[tokio::test]
+    async fn init_overwrite_always() {
+        let (mut cache, date) = create_live_cache(InitPolicy::OverwriteAlways);
+
+        cache.init(CandleData::new(date + Duration::seconds(20), 5.0, 1.0));
+
+        let candle = cache.prices_by_date.get(&date.timestamp()).unwrap();
+        assert_eq!(candle.close, 5.0);
+        assert_eq!(candle.volume, 1.0);
+    }
+
+    #[tokio::test]
+    async fn init_keep_newer() {
+        let (mut cache, date) = create_live_cache(InitPolicy::KeepNewer);
+
+        cache.init(CandleData::new(date + Duration::seconds(20), 5.0, 1.0));
+
+        let candle = cache.prices_by_date.get(&date.timestamp()).unwrap();
+        assert_eq!(candle.close, 11.0);
+        assert_eq!(candle.volume, 3.0);
+
+        cache.init(CandleData::new(date + Duration::seconds(50), 7.0, 4.0));
+
+        let candle = cache.prices_by_date.get(&date.timestamp()).unwrap();
+        assert_eq!(candle.close, 7.0);
+        assert_eq!(candle.volume, 4.0);
+    }
+
+    #[tokio::test]
+    async fn init_merge() {
+        let (mut cache, date) = create_live_cache(InitPolicy::Merge);
+        let mut stored = CandleData::new(date, 9.0, 1.0);
+        stored.update(date + Duration::seconds(20), 12.0, 1.0);
+
+        cache.init(stored);
+
+        let candle = cache.prices_by_date.get(&date.timestamp()).unwrap();
+        assert_eq!(candle.open, 9.0);
+        assert_eq!(candle.close, 11.0);
+        assert_eq!(candle.high, 12.0);
+        assert_eq!(candle.low, 9.0);
+        assert_eq!(candle.volume, 3.0);
+        assert_eq!(candle.datetime, date + Duration::seconds(40));
+    }
+
+    #[tokio::test]
+    async fn iter_chunks() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..25 {
+            cache.update(from + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let chunks: Vec<Vec<CandleData>> = cache
+            .iter_chunks(from, from + Duration::minutes(25), 10)
+            .collect();
+        let sizes: Vec<usize> = chunks.iter().map(|chunk| chunk.len()).collect();
+
+        assert_eq!(sizes, vec![10, 10, 5]);
+        assert_eq!(chunks[1][0].close, 10.0);
+        assert_eq!(chunks[2][4].close, 24.0);
+        assert_eq!(cache.iter_chunks(from, from + Duration::minutes(25), 0).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn prev_and_next() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(from, 1.0, 1.0);
+        cache.update(from + Duration::minutes(2), 2.0, 1.0);
+        cache.update(from + Duration::minutes(5), 3.0, 1.0);
+
+        let middle = from + Duration::minutes(2) + Duration::seconds(30);
+
+        assert_eq!(cache.prev(middle).map(|candle| candle.close), Some(1.0));
+        assert_eq!(cache.next(middle).map(|candle| candle.close), Some(3.0));
+        assert!(cache.prev(from).is_none());
+        assert!(cache.next(from + Duration::minutes(5)).is_none());
+    }
+
+    #[tokio::test]
+    async fn digest() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let mut other = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..5 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+            other.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        assert_eq!(cache.digest(), other.digest());
+
+        other.update(date + Duration::minutes(4), 4.0, 1.0);
+
+        assert_ne!(cache.digest(), other.digest());
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_min_volume() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 1.0, 5.0);
+        cache.update(date + Duration::minutes(1), 2.0, 0.5);
+        cache.update(date + Duration::minutes(2), 3.0, 2.0);
+        cache.update(date + Duration::minutes(2), 3.0, 1.0);
+
+        let candles = cache.get_by_date_range_min_volume(date, date + Duration::minutes(3), 3.0);
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+
+        assert_eq!(closes, vec![1.0, 3.0]);
+    }
+
+    #[tokio::test]
+    async fn rebucket() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 2.0, 1.0);
+        cache.update(date + Duration::minutes(10), 5.0, 2.0);
+        cache.update(date + Duration::minutes(20), 1.0, 3.0);
+        cache.update(date + Duration::minutes(59), 3.0, 4.0);
+        cache.update(date + Duration::minutes(60), 7.0, 5.0);
+
+        cache.rebucket(CandleType::Hour);
+
+        assert_eq!(cache.candle_type, CandleType::Hour);
+        assert_eq!(cache.prices_by_date.len(), 2);
+
+        let first = cache.prices_by_date.get(&date.timestamp()).unwrap();
+        assert_eq!(first.open, 2.0);
+        assert_eq!(first.close, 3.0);
+        assert_eq!(first.high, 5.0);
+        assert_eq!(first.low, 1.0);
+        assert_eq!(first.volume, 10.0);
+
+        let second = cache.prices_by_date.get(&(date + Duration::hours(1)).timestamp()).unwrap();
+        assert_eq!(second.open, 7.0);
+        assert_eq!(second.volume, 5.0);
+    }
+
+    #[tokio::test]
+    async fn entries_in_range() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let entries = cache.entries_in_range(date, date + Duration::minutes(3));
+
+        assert_eq!(entries.len(), 3);
+
+        for (timestamp, candle) in entries {
+            assert_eq!(timestamp, candle.get_candle_date(CandleType::Minute).timestamp());
+        }
+    }
+
+    #[tokio::test]
+    async fn update_ignores_ticks_older_than_window() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        cache.window = Some(Duration::hours(1));
+        let now = Utc::now();
+
+        assert!(!cache.update(now - Duration::hours(2), 1.0, 1.0));
+        assert!(cache.prices_by_date.is_empty());
+
+        assert!(cache.update(now, 1.0, 1.0));
+        assert_eq!(cache.prices_by_date.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn stats() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let closes = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+
+        for (i, close) in closes.iter().enumerate() {
+            cache.update(date + Duration::minutes(i as i64), *close, 1.5);
+        }
+
+        cache.update(date + Duration::seconds(30), 1.0, 0.5);
+        cache.update(date + Duration::seconds(40), 2.0, 0.0);
+
+        let stats = cache.stats(date, date + Duration::minutes(8)).unwrap();
+
+        assert_eq!(stats.count, 8);
+        assert_eq!(stats.total_volume, 12.5);
+        assert_eq!(stats.average_close, 5.0);
+        assert_eq!(stats.min, 1.0);
+        assert_eq!(stats.max, 9.0);
+        assert_eq!(stats.close_std_dev, 2.0);
+        assert_eq!(cache.stats(date + Duration::hours(1), date + Duration::hours(2)), None);
+    }
+
+    #[cfg(feature = "prost")]
+    #[tokio::test]
+    async fn proto_round_trip() {
+        use crate::models::candle_proto::CandlePricesProto;
+        use prost::Message;
+
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+            cache.update(date + Duration::minutes(i) + Duration::milliseconds(1500), i as f64 + 0.5, 2.0);
+        }
+
+        cache.init(CandleData::flat(date + Duration::minutes(3), 2.5));
+
+        let bytes = cache.to_proto(date, date + Duration::minutes(4)).encode_to_vec();
+        let proto = CandlePricesProto::decode(bytes.as_slice()).unwrap();
+        let restored = CandlePricesCache::from_proto(&proto).unwrap();
+
+        assert_eq!(restored.candle_type, CandleType::Minute);
+        assert_eq!(restored.digest(), cache.digest());
+        assert_eq!(
+            restored.prices_by_date.values().map(|candle| candle.datetime).collect::<Vec<_>>(),
+            cache.prices_by_date.values().map(|candle| candle.datetime).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            restored.prices_by_date.values().map(|candle| candle.tick_count).collect::<Vec<_>>(),
+            vec![2, 2, 2, 0]
+        );
+
+        let mut legacy = proto.clone();
+        legacy.candles[0].tick_count = None;
+        let legacy = CandlePricesProto::decode(legacy.encode_to_vec().as_slice()).unwrap();
+
+        assert_eq!(CandlePricesCache::from_proto(&legacy).unwrap().prices_by_date.values().next().unwrap().tick_count, 1);
+
+        let mut corrupted = proto;
+        corrupted.candles[1].open_datetime_ms = i64::MAX;
+
+        assert!(CandlePricesCache::from_proto(&corrupted).is_none());
+    }
+
+    #[tokio::test]
+    async fn get_realigned() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..180 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let candles = cache.get_realigned(
+            date,
+            date + Duration::hours(3),
+            date + Duration::minutes(30),
+            CandleType::Hour,
+        );
+
+        assert_eq!(candles.len(), 4);
+        assert_eq!((candles[0].open, candles[0].close, candles[0].volume), (0.0, 29.0, 30.0));
+        assert_eq!((candles[1].open, candles[1].close, candles[1].volume), (30.0, 89.0, 60.0));
+        assert_eq!((candles[1].low, candles[1].high), (30.0, 89.0));
+        assert_eq!((candles[3].open, candles[3].close, candles[3].volume), (150.0, 179.0, 30.0));
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_capped() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..100 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let to = date + Duration::minutes(100);
+        let candles = cache.get_by_date_range_capped(date, to, 10);
+
+        assert_eq!(candles, cache.get_by_date_range(date, date + Duration::minutes(10)));
+        assert!(cache.get_by_date_range_capped(date, to, 0).is_empty());
+        assert_eq!(cache.get_by_date_range_capped(date + Duration::minutes(95), to, 10).len(), 5);
+
+        let mut visited = 0;
+        let candles = take_capped(cache.iter_by_date_range(date, to).inspect(|_candle| visited += 1), 10);
+
+        assert_eq!(candles.len(), 10);
+        assert_eq!(visited, 10);
+    }
+
+    #[tokio::test]
+    async fn fill_missing() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), 1.0, 1.0);
+        }
+
+        let candles: Vec<CandleData> = (0..6)
+            .map(|i| CandleData::new(date + Duration::minutes(i), 2.0, 1.0))
+            .collect();
+
+        assert_eq!(cache.fill_missing(&candles), 3);
+        assert_eq!(cache.prices_by_date.len(), 6);
+        assert_eq!(cache.prices_by_date.get(&date.timestamp()).unwrap().close, 1.0);
+        assert_eq!(cache.prices_by_date.get(&(date + Duration::minutes(5)).timestamp()).unwrap().close, 2.0);
+        assert_eq!(cache.fill_missing(&candles), 0);
+    }
+
+    #[tokio::test]
+    async fn get_by_bounds() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..4 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let from = date + Duration::minutes(1);
+        let to = date + Duration::minutes(3);
+        let get_closes = |from, to| -> Vec<f64> {
+            cache
+                .get_by_bounds(Bounds { from, to })
+                .iter()
+                .map(|candle| candle.close)
+                .collect()
+        };
+
+        assert_eq!(get_closes(Bound::Included(from), Bound::Excluded(to)), vec![1.0, 2.0]);
+        assert_eq!(get_closes(Bound::Included(from), Bound::Included(to)), vec![1.0, 2.0, 3.0]);
+        assert_eq!(get_closes(Bound::Excluded(from), Bound::Included(to)), vec![2.0, 3.0]);
+        assert_eq!(get_closes(Bound::Excluded(from), Bound::Excluded(to)), vec![2.0]);
+        assert_eq!(get_closes(Bound::Unbounded, Bound::Excluded(from)), vec![0.0]);
+        assert!(get_closes(Bound::Excluded(from), Bound::Excluded(from)).is_empty());
+        assert!(get_closes(Bound::Included(to), Bound::Included(from)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_sessions() {
+        let mut cache = CandlePricesCache::new(CandleType::Session);
+        cache.sessions = Some(TradingSessions::new(vec![
+            TradingSession {
+                name: "London".to_string(),
+                start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+            },
+            TradingSession {
+                name: "NewYork".to_string(),
+                start: NaiveTime::from_hms_opt(13, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            },
+        ]));
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 3, 0, 0, 0).unwrap();
+
+        assert!(cache.update(date + Duration::hours(9), 1.0, 1.0));
+        assert!(cache.update(date + Duration::hours(12), 3.0, 1.0));
+        assert!(cache.update(date + Duration::hours(14), 5.0, 1.0));
+        assert!(cache.update(date + Duration::hours(20), 4.0, 1.0));
+        assert!(!cache.update(date + Duration::hours(22), 9.0, 1.0));
+
+        let london = cache.prices_by_date.get(&(date + Duration::hours(8)).timestamp()).unwrap();
+        let new_york = cache.prices_by_date.get(&(date + Duration::hours(13)).timestamp()).unwrap();
+
+        assert_eq!(cache.prices_by_date.len(), 2);
+        assert_eq!((london.open, london.close, london.volume), (1.0, 3.0, 2.0));
+        assert_eq!((new_york.open, new_york.close, new_york.high), (5.0, 4.0, 5.0));
+    }
+
+    #[tokio::test]
+    async fn get_latest_n_closed() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..5 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let now = date + Duration::minutes(4) + Duration::seconds(30);
+        let candles = cache.get_latest_n_closed(2, now);
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+
+        assert_eq!(closes, vec![2.0, 3.0]);
+        assert_eq!(cache.get_latest_n_closed(10, now).len(), 4);
+        assert_eq!(cache.get_latest_n_closed(2, date + Duration::minutes(5)).last().unwrap().close, 4.0);
+
+        let mut late_tick = CandleData::new(date + Duration::minutes(6) + Duration::seconds(10), 6.0, 1.0);
+        late_tick.open_datetime = date + Duration::minutes(5);
+        cache.prices_by_date.insert((date + Duration::minutes(5)).timestamp(), late_tick);
+
+        let now = date + Duration::minutes(6) + Duration::seconds(30);
+        assert_eq!(cache.get_latest_n_closed(1, now)[0].close, 6.0);
+    }
+
+    #[tokio::test]
+    async fn delta_bytes_round_trip() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..100 {
+            for tick in 0..4 {
+                let price = ((1.08123 + ((i * 7 + tick * 3) % 11 - 5) as f64 * 0.00002) * 100_000.0).round() / 100_000.0;
+                let tick_date = date + Duration::minutes(i) + Duration::milliseconds(1500 + tick * 14_250);
+
+                cache.update(tick_date, price, 0.5 + tick as f64);
+            }
+        }
+
+        let to = date + Duration::minutes(100);
+        let bytes = cache.to_delta_bytes(date, to);
+        let restored = CandlePricesCache::from_delta_bytes(&bytes).unwrap();
+        let candles = cache.get_by_date_range(date, to);
+        let json_size = serde_json::to_vec(&candles).unwrap().len();
+        // to_bytes stores both sides, so it's compared with delta bytes of bid and ask caches
+        let binary_size: usize = candles
+            .iter()
+            .map(|candle| {
+                BidAskCandle {
+                    candle_type: CandleType::Minute,
+                    datetime: date,
+                    instrument: "EURUSD".into(),
+                    bid_data: candle.clone(),
+                    ask_data: candle.clone(),
+                }
+                .to_bytes()
+                .len()
+            })
+            .sum();
+
+        assert_eq!(restored.candle_type, CandleType::Minute);
+        assert_eq!(restored.digest(), cache.digest());
+        assert_eq!(restored.get_by_date_range(date, to), candles);
+        assert!(2 * bytes.len() * 4 < binary_size, "{} vs {}", bytes.len(), binary_size);
+        assert!(bytes.len() * 7 < json_size, "{} vs {}", bytes.len(), json_size);
+        assert!(CandlePricesCache::from_delta_bytes(&bytes[..bytes.len() - 1]).is_none());
+
+        let mut flat = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..100 {
+            flat.update(date + Duration::minutes(i), 1.08123, 1.0);
+        }
+
+        let flat_bytes = flat.to_delta_bytes(date, to);
+        let first_candle_size = flat.to_delta_bytes(date, date + Duration::minutes(1)).len();
+
+        // each repeated candle: timestamp delta, 5 unchanged fields, tick count and 2 dates of a byte each
+        assert_eq!(flat_bytes.len(), first_candle_size + 99 * 9);
+
+        let mut irrational = CandlePricesCache::new(CandleType::Minute);
+        irrational.update(date, 1.0 / 3.0, 0.1);
+        irrational.update(date + Duration::minutes(1), f64::NAN, -0.0);
+
+        let restored = CandlePricesCache::from_delta_bytes(&irrational.to_delta_bytes(date, to)).unwrap();
+
+        assert_eq!(restored.get_by_date_range(date, to)[0], irrational.get_by_date_range(date, to)[0]);
+        assert!(restored.get_by_date_range(date, to)[1].close.is_nan());
+        assert!(restored.get_by_date_range(date, to)[1].volume.is_sign_negative());
+    }
+
+    #[tokio::test]
+    async fn get_dense() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 1.0, 1.0);
+        cache.update(date + Duration::minutes(2), 3.0, 1.0);
+
+        let dense = cache.get_dense(date, date + Duration::minutes(3));
+
+        assert_eq!(dense.len(), 3);
+        assert_eq!(dense[0].as_ref().unwrap().close, 1.0);
+        assert!(dense[1].is_none());
+        assert_eq!(dense[2].as_ref().unwrap().close, 3.0);
+    }
+
+    #[tokio::test]
+    async fn upsert_authoritative() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date + Duration::seconds(5), 1.0, 1.0);
+        cache.update(date + Duration::seconds(10), 2.0, 1.0);
+
+        let mut authoritative = CandleData::new(date, 1.1, 3.0);
+        authoritative.close = 1.9;
+
+        assert!(cache.upsert_authoritative(authoritative.clone()));
+        let digest = cache.digest();
+
+        assert!(!cache.upsert_authoritative(authoritative.clone()));
+        assert_eq!(cache.digest(), digest);
+        assert_eq!(cache.prices_by_date.len(), 1);
+        assert_eq!(cache.prices_by_date[&date.timestamp()], authoritative);
+    }
+
+    #[tokio::test]
+    async fn get_rth() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 3, 0, 0, 0).unwrap();
+        let calendar = MarketCalendar::new(
+            TradingSession {
+                name: "NYSE".to_string(),
+                start: NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            },
+            vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+        );
+        let mut cache = CandlePricesCache::new(CandleType::ThirtyMinutes);
+
+        for hour in [13, 14, 15, 20, 21, 22] {
+            cache.update(date + Duration::hours(hour) + Duration::minutes(30), hour as f64, 1.0);
+        }
+
+        let closes: Vec<f64> = cache
+            .get_rth(date, date + Duration::days(1), &calendar)
+            .iter()
+            .map(|candle| candle.close)
+            .collect();
+
+        assert_eq!(closes, vec![14.0, 15.0, 20.0]);
+    }
+
+    #[tokio::test]
+    async fn get_recent() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..120 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let now = date + Duration::minutes(119) + Duration::seconds(30);
+        let candles = cache.get_recent(Duration::hours(1), now);
+
+        assert_eq!(candles.len(), 60);
+        assert_eq!(candles.first().unwrap().close, 60.0);
+        assert_eq!(candles.last().unwrap().close, 119.0);
+    }
+
+    #[tokio::test]
+    async fn open_mode_previous_close() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut gapped = CandlePricesCache::new(CandleType::Minute);
+        let mut continuous = CandlePricesCache::new(CandleType::Minute);
+        continuous.open_mode = OpenMode::PreviousClose;
+
+        for cache in [&mut gapped, &mut continuous] {
+            cache.update(date, 1.0, 1.0);
+            cache.update(date + Duration::seconds(30), 1.5, 1.0);
+            cache.update(date + Duration::minutes(3), 2.0, 1.0);
+        }
+
+        let gapped_candle = &gapped.prices_by_date[&(date + Duration::minutes(3)).timestamp()];
+        let continuous_candle = &continuous.prices_by_date[&(date + Duration::minutes(3)).timestamp()];
+
+        assert_eq!(gapped_candle.open, 2.0);
+        assert_eq!(continuous_candle.open, 1.5);
+        assert_eq!(continuous_candle.low, 1.5);
+        assert_eq!(continuous_candle.high, 2.0);
+        assert_eq!(continuous.prices_by_date[&date.timestamp()].open, 1.0);
+    }
+
+    #[tokio::test]
+    async fn snapshot_until() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let (candles, is_last_partial) = cache.snapshot_until(date + Duration::minutes(1) + Duration::seconds(30));
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles.last().unwrap().close, 1.0);
+        assert!(is_last_partial);
+
+        let (candles, is_last_partial) = cache.snapshot_until(date + Duration::minutes(2));
+
+        assert_eq!(candles.len(), 2);
+        assert!(!is_last_partial);
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_with_context() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..10 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let (candles, range_start) =
+            cache.get_by_date_range_with_context(date + Duration::minutes(5), date + Duration::minutes(8), 2);
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+
+        assert_eq!(closes, vec![3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(range_start, 2);
+
+        let (candles, range_start) =
+            cache.get_by_date_range_with_context(date + Duration::minutes(1), date + Duration::minutes(2), 2);
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(range_start, 1);
+    }
+
+    #[tokio::test]
+    async fn detect_spikes() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..10 {
+            cache.update(date + Duration::minutes(i), 100.0 + (i % 2) as f64, 1.0);
+        }
+
+        cache.update(date + Duration::minutes(4) + Duration::seconds(10), 150.0, 1.0);
+        cache.update(date + Duration::minutes(4) + Duration::seconds(20), 100.0, 1.0);
+
+        let spikes = cache.detect_spikes(date, date + Duration::minutes(10), 5.0);
+
+        assert_eq!(spikes, vec![date + Duration::minutes(4)]);
+    }
+
+    #[tokio::test]
+    async fn volume_modes() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let expected = [(VolumeMode::Sum, 10.0), (VolumeMode::Last, 3.0), (VolumeMode::Max, 4.0)];
+
+        for (volume_mode, expected_volume) in expected {
+            let mut cache = CandlePricesCache::new(CandleType::Minute);
+            cache.volume_mode = volume_mode;
+
+            for (i, volume) in [2.0, 4.0, 1.0, 3.0].into_iter().enumerate() {
+                cache.update(date + Duration::seconds(i as i64 * 10), 1.0, volume);
+            }
+
+            assert_eq!(cache.prices_by_date[&date.timestamp()].volume, expected_volume, "{:?}", volume_mode);
+        }
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_cow() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let candles = cache.get_by_date_range_cow(date, date + Duration::minutes(2));
+
+        assert_eq!(candles.len(), 2);
+        assert!(std::ptr::eq(candles[1].as_ref(), &cache.prices_by_date[&(date + Duration::minutes(1)).timestamp()]));
+
+        let mut owned: Vec<CandleData> = candles.into_iter().map(|candle| candle.into_owned()).collect();
+        owned[0].close = 10.0;
+        cache.update(date + Duration::seconds(10), 5.0, 1.0);
+
+        assert_eq!(owned[0].close, 10.0);
+        assert_eq!(cache.prices_by_date[&date.timestamp()].close, 5.0);
+    }
+
+    #[tokio::test]
+    async fn anchored_vwap() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date - Duration::minutes(1), 100.0, 100.0);
+        cache.update(date, 5.0, 0.0);
+        cache.update(date + Duration::minutes(1), 2.0, 1.0);
+        cache.update(date + Duration::minutes(2), 5.0, 3.0);
+        cache.update(date + Duration::minutes(3), 1.0, 4.0);
+
+        let vwap = cache.anchored_vwap(date, date + Duration::minutes(3));
+
+        assert_eq!(
+            vwap,
+            vec![
+                (date + Duration::minutes(1), 2.0),
+                (date + Duration::minutes(2), (2.0 + 15.0) / 4.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_inclusive() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..3 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let date_to = CandleType::Minute.get_end_date(date + Duration::seconds(30));
+
+        assert_eq!(cache.get_by_date_range(date, date_to).len(), 1);
+        assert_eq!(cache.get_by_date_range_inclusive(date, date_to).len(), 2);
+        assert_eq!(cache.get_by_date_range_inclusive(date_to, date_to)[0].close, 1.0);
+        assert!(cache.get_by_date_range_inclusive(date_to, date).is_empty());
+    }
+
+    #[tokio::test]
+    async fn resample() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        for i in 0..120 {
+            cache.update(date + Duration::minutes(i), (i % 50) as f64, 2.0);
+        }
+
+        let hours = cache.resample(CandleType::Hour).unwrap();
+        let candles = hours.get_by_date_range(date, date + Duration::hours(2));
+
+        assert_eq!(hours.candle_type, CandleType::Hour);
+        assert_eq!(cache.prices_by_date.len(), 120);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            (candles[1].open, candles[1].high, candles[1].low, candles[1].close, candles[1].volume),
+            (10.0, 49.0, 0.0, 19.0, 120.0)
+        );
+        assert!(cache.resample(CandleType::Minute).is_none());
+        assert!(hours.resample(CandleType::FiveMinutes).is_none());
+    }
+
+    #[tokio::test]
+    async fn resample_only_nesting_types() {
+        let nesting = [
+            (CandleType::Minute, CandleType::Month),
+            (CandleType::FifteenMinutes, CandleType::Week),
+            (CandleType::EightHours, CandleType::Day),
+            (CandleType::Day, CandleType::ThreeDays),
+            (CandleType::Day, CandleType::Week),
+            (CandleType::Day, CandleType::Month),
+            (CandleType::Hour, CandleType::Session),
+        ];
+        let non_nesting = [
+            (CandleType::Week, CandleType::Month),
+            (CandleType::ThreeDays, CandleType::Week),
+            (CandleType::ThreeDays, CandleType::Month),
+            (CandleType::SevenDays, CandleType::Month),
+            (CandleType::SevenDays, CandleType::Week),
+            (CandleType::Session, CandleType::Day),
+            (CandleType::Month, CandleType::Month),
+            (CandleType::Hour, CandleType::Hour),
+        ];
+
+        for (source, target) in nesting {
+            assert!(CandlePricesCache::new(source.clone()).resample(target.clone()).is_some(), "{source} -> {target}");
+        }
+
+        for (source, target) in non_nesting {
+            assert!(CandlePricesCache::new(source.clone()).resample(target.clone()).is_none(), "{source} -> {target}");
+        }
+
+        let mut cache = CandlePricesCache::new(CandleType::Hour);
+        cache.day_offset = FixedOffset::east_opt(2 * 3600);
+        assert!(cache.resample(CandleType::Month).is_some());
+
+        cache.day_offset = FixedOffset::east_opt(1800);
+        assert!(cache.resample(CandleType::Day).is_none());
+        assert!(cache.resample(CandleType::Month).is_none());
+
+        let mut cache = CandlePricesCache::new(CandleType::Day);
+        cache.day_offset = FixedOffset::east_opt(2 * 3600);
+        assert!(cache.resample(CandleType::Week).is_none());
+
+        let mut cache = CandlePricesCache::new(CandleType::Hour);
+        cache.sessions = Some(TradingSessions::new(vec![TradingSession {
+            name: "London".to_string(),
+            start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(16, 30, 0).unwrap(),
+        }]));
+        assert!(cache.resample(CandleType::Session).is_none());
+
+        cache.candle_type = CandleType::ThirtyMinutes;
+        assert!(cache.resample(CandleType::Session).is_some());
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_filled() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date + Duration::minutes(2), 1.0, 1.0);
+        cache.update(date + Duration::minutes(2) + Duration::seconds(30), 2.0, 1.0);
+        cache.update(date + Duration::minutes(5), 3.0, 1.0);
+
+        let candles = cache.get_by_date_range_filled(date, date + Duration::minutes(8));
+        let closes: Vec<f64> = candles.iter().map(|candle| candle.close).collect();
+
+        assert_eq!(closes, vec![2.0, 2.0, 2.0, 3.0, 3.0, 3.0]);
+
+        let gap = &candles[1];
+        assert_eq!((gap.open, gap.high, gap.low, gap.volume), (2.0, 2.0, 2.0, 0.0));
+        assert_eq!(gap.tick_count, 0);
+        assert_eq!((candles[0].tick_count, candles[2].tick_count, candles[3].tick_count), (2, 0, 1));
+        assert_eq!(gap.datetime, date + Duration::minutes(3));
+        assert_eq!(candles[5].datetime, date + Duration::minutes(7));
+        assert!(cache.get_by_date_range_filled(date, date + Duration::minutes(2)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_to_now() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 1.0, 1.0);
+        cache.update(date + Duration::minutes(1), 2.0, 1.0);
+
+        let now = date + Duration::minutes(3) + Duration::seconds(15);
+        let candles = cache.get_by_date_range_to_now(date, now);
+
+        assert_eq!(candles.len(), 4);
+        assert_eq!(candles[2].datetime, date + Duration::minutes(2));
+        assert_eq!(candles[3].datetime, date + Duration::minutes(3));
+        assert!(candles[2..].iter().all(|candle| candle.open == 2.0 && candle.close == 2.0 && candle.volume == 0.0));
+        assert!(candles[2..].iter().all(|candle| candle.tick_count == 0));
+
+        cache.update(now, 3.0, 1.0);
+
+        assert_eq!(cache.get_by_date_range_to_now(date, now).len(), 3);
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_to_now_from_gap() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 1.0, 1.0);
+        cache.update(date + Duration::minutes(4), 2.0, 1.0);
+
+        let candles = cache.get_by_date_range_to_now(date + Duration::minutes(2), date + Duration::minutes(5) + Duration::seconds(10));
+
+        assert_eq!(
+            candles.iter().map(|candle| (candle.datetime, candle.close, candle.tick_count)).collect::<Vec<_>>(),
+            vec![
+                (date + Duration::minutes(2), 1.0, 0),
+                (date + Duration::minutes(3), 1.0, 0),
+                (date + Duration::minutes(4), 2.0, 1),
+                (date + Duration::minutes(5), 2.0, 0),
+            ]
+        );
+
+        let candles = cache.get_by_date_range_to_now(date + Duration::minutes(6), date + Duration::minutes(7) + Duration::seconds(10));
+
+        assert_eq!(
+            candles.iter().map(|candle| (candle.datetime, candle.close)).collect::<Vec<_>>(),
+            vec![(date + Duration::minutes(6), 2.0), (date + Duration::minutes(7), 2.0)]
+        );
+        assert!(CandlePricesCache::new(CandleType::Minute).get_by_date_range_to_now(date, date + Duration::minutes(3)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn equality() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let build = || {
+            let mut cache = CandlePricesCache::new(CandleType::Minute);
+            for i in 0..5 {
+                cache.update(date + Duration::seconds(i * 30), 1.0 + i as f64, 1.0);
+            }
+            cache
+        };
+
+        let mut other = build();
+        other.volume_mode = VolumeMode::Max;
+        assert_eq!(build(), other);
+
+        other.update(date + Duration::minutes(2), 10.0, 1.0);
+        assert_ne!(build(), other);
+        assert_ne!(build(), CandlePricesCache::new(CandleType::Minute));
+    }
+
+    #[tokio::test]
+    async fn lightweight_chart_json() {
+        let mut cache = CandlePricesCache::new(CandleType::Hour);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(date + Duration::minutes(10), 1.0, 2.0);
+        cache.update(date + Duration::minutes(20), 1.5, 3.0);
+        cache.update(date + Duration::minutes(70), 2.0, 4.0);
+        cache.update(date + Duration::minutes(130), 3.0, 1.0);
+
+        let to = date + Duration::hours(2);
+
+        assert_eq!(
+            cache.to_lightweight_chart_json(date, to),
+            r#"[{"time":946684800,"open":1.0,"high":1.5,"low":1.0,"close":1.5},{"time":946688400,"open":2.0,"high":2.0,"low":2.0,"close":2.0}]"#
+        );
+        assert_eq!(
+            cache.to_lightweight_chart_volume_json(date, to),
+            r#"[{"time":946684800,"value":5.0},{"time":946688400,"value":4.0}]"#
+        );
+        assert_eq!(cache.to_lightweight_chart_json(to + Duration::hours(1), to + Duration::hours(2)), "[]");
+    }
+
+    #[tokio::test]
+    async fn estimated_bytes() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(cache.estimated_bytes(), 0);
+
+        for i in 0..100 {
+            cache.update(date + Duration::minutes(i), 1.0, 1.0);
+        }
+
+        let bytes_100 = cache.estimated_bytes();
+
+        for i in 100..200 {
+            cache.update(date + Duration::minutes(i), 1.0, 1.0);
+        }
+
+        assert_eq!(cache.len(), 200);
+        assert!(bytes_100 >= 100 * std::mem::size_of::<CandleData>());
+        assert_eq!(cache.estimated_bytes(), 2 * bytes_100);
+    }
+
+    #[tokio::test]
+    async fn update_with_day_offset() {
+        let mut cache = CandlePricesCache::new(CandleType::Day);
+        cache.day_offset = FixedOffset::east_opt(2 * 3600);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(date + Duration::hours(21), 1.0, 1.0);
+        cache.update(date + Duration::hours(23), 2.0, 1.0);
+        cache.update(date + Duration::hours(30), 3.0, 1.0);
+
+        let candles = cache.get_by_date_range(date - Duration::days(1), date + Duration::days(2));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(candles[0].close, 1.0);
+        assert_eq!(candles[1].open, 2.0);
+        assert_eq!(candles[1].close, 3.0);
+        assert_eq!(
+            cache.prices_by_date.keys().copied().collect::<Vec<_>>(),
+            vec![(date - Duration::hours(2)).timestamp(), (date + Duration::hours(22)).timestamp()]
+        );
+    }
+
+    #[tokio::test]
+    async fn extract_before() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..10 {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        let cutoff = date + Duration::minutes(4);
+        let extracted = cache.extract_before(cutoff);
+
+        assert_eq!(extracted.iter().map(|candle| candle.close).collect::<Vec<_>>(), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(cache.len(), 6);
+        assert_eq!(cache.get_by_date_range(date, cutoff), vec![]);
+        assert_eq!(cache.get_by_date_range(cutoff, cutoff + Duration::minutes(1))[0].close, 4.0);
+        assert!(cache.extract_before(cutoff).is_empty());
+    }
+
+    #[tokio::test]
+    async fn tick_count() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..25 {
+            cache.update(date + Duration::seconds(i * 5), 1.0, 1.0);
+        }
+
+        let minutes = cache.get_by_date_range(date, date + Duration::hours(1));
+
+        assert_eq!(minutes.iter().map(|candle| candle.tick_count).collect::<Vec<_>>(), vec![12, 12, 1]);
+
+        let hours = cache.resample(CandleType::Hour).unwrap();
+        assert_eq!(hours.get_by_date_range(date, date + Duration::hours(1))[0].tick_count, 25);
+
+        let restored = CandlePricesCache::from_delta_bytes(&cache.to_delta_bytes(date, date + Duration::hours(1))).unwrap();
+        assert_eq!(restored, cache);
+
+        cache.init_policy = InitPolicy::Merge;
+        let mut snapshot = minutes[0].clone();
+        snapshot.tick_count = 5;
+        cache.init(snapshot);
+
+        assert_eq!(cache.get_by_date_range(date, date + Duration::minutes(1))[0].tick_count, 12);
+    }
+
+    #[tokio::test]
+    async fn iter_by_date_range() {
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in (0..20).filter(|i| i % 3 != 0) {
+            cache.update(date + Duration::minutes(i), i as f64, 1.0);
+        }
+
+        for (from, to) in [(0, 20), (4, 11), (5, 6), (30, 40)] {
+            let from = date + Duration::minutes(from);
+            let to = date + Duration::minutes(to);
+            let borrowed: Vec<&CandleData> = cache.iter_by_date_range(from, to).collect();
+
+            assert_eq!(borrowed, cache.get_by_date_range(from, to).iter().collect::<Vec<_>>());
+        }
+
+        assert_eq!(cache.iter_by_date_range(date, date + Duration::minutes(6)).count(), 4);
+    }
+
+    #[tokio::test]
+    async fn filled_ranges_with_day_offset() {
+        let mut cache = CandlePricesCache::new(CandleType::Day);
+        cache.day_offset = FixedOffset::east_opt(2 * 3600);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let day_start = date - Duration::hours(2);
+
+        cache.update(date, 1.0, 1.0);
+        cache.update(date + Duration::days(2), 3.0, 1.0);
+        cache.update(date + Duration::days(3), 4.0, 1.0);
+
+        let filled = cache.get_by_date_range_filled(date, date + Duration::days(4));
+
+        assert_eq!(filled.iter().map(|candle| candle.close).collect::<Vec<_>>(), vec![1.0, 1.0, 3.0, 4.0, 4.0]);
+        assert_eq!(filled[1].datetime, day_start + Duration::days(1));
+        assert_eq!(filled[4].datetime, day_start + Duration::days(4));
+        assert_eq!(filled[1].volume, 0.0);
+        assert_eq!(cache.get_dense(date, date + Duration::days(4)).iter().filter(|candle| candle.is_some()).count(), 3);
+
+        let now = date + Duration::days(5) + Duration::hours(23);
+        let to_now = cache.get_by_date_range_to_now(day_start, now);
+        let dates: Vec<DateTime<Utc>> = to_now.iter().skip(3).map(|candle| candle.datetime).collect();
+
+        assert_eq!(to_now.len(), 3 + 3);
+        assert_eq!(dates, vec![day_start + Duration::days(4), day_start + Duration::days(5), day_start + Duration::days(6)]);
+        assert!(to_now.windows(2).all(|pair| pair[0].datetime < pair[1].datetime));
+    }
+
+    #[tokio::test]
+    async fn filled_ranges_with_sessions() {
+        let mut cache = CandlePricesCache::new(CandleType::Session);
+        cache.sessions = Some(TradingSessions::new(vec![
+            TradingSession {
+                name: "London".to_string(),
+                start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            },
+            TradingSession {
+                name: "NewYork".to_string(),
+                start: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            },
+        ]));
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.update(date + Duration::hours(9), 1.0, 1.0);
+        cache.update(date + Duration::days(1) + Duration::hours(17), 2.0, 1.0);
+
+        let filled = cache.get_by_date_range_filled(date, date + Duration::days(2));
+
+        assert_eq!(filled.iter().map(|candle| candle.close).collect::<Vec<_>>(), vec![1.0, 1.0, 1.0, 2.0]);
+        assert_eq!(filled[1].datetime, date + Duration::hours(16));
+        assert_eq!(filled[2].datetime, date + Duration::days(1) + Duration::hours(8));
+
+        let to_now = cache.get_by_date_range_to_now(date, date + Duration::days(2) + Duration::hours(10));
+        let tail: Vec<DateTime<Utc>> = to_now.iter().skip(2).map(|candle| candle.datetime).collect();
+
+        assert_eq!(tail, vec![date + Duration::days(2) + Duration::hours(8)]);
+    }
+}
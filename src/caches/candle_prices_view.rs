@@ -0,0 +1,73 @@
+use chrono::{DateTime, TimeZone, Utc};
+use crate::{caches::candle_prices_cache::CandlePricesCache, models::candle_data::CandleData};
+
+/// Read-only handle of the cache: exposes only queries, so handlers can't mutate candles
+///
+/// ```compile_fail
+/// use candles_shared::{caches::candle_prices_cache::CandlePricesCache, models::candle_type::CandleType};
+///
+/// let mut cache = CandlePricesCache::new(CandleType::Minute);
+/// let view = cache.view();
+/// view.cache.clear();
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CandlePricesView<'a> {
+    cache: &'a CandlePricesCache,
+}
+
+impl<'a> CandlePricesView<'a> {
+    pub fn new(cache: &'a CandlePricesCache) -> Self {
+        Self { cache }
+    }
+
+    pub fn get_by_date_range(&self, date_from: DateTime<Utc>, date_to: DateTime<Utc>) -> Vec<CandleData> {
+        self.cache.get_by_date_range(date_from, date_to)
+    }
+
+    /// Gets the most recent candle
+    pub fn get_latest(&self) -> Option<&'a CandleData> {
+        self.cache.prices_by_date.values().next_back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cache.prices_by_date.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.prices_by_date.is_empty()
+    }
+
+    /// Gets start dates of the first and the last candles
+    pub fn time_range(&self) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let first = *self.cache.prices_by_date.keys().next()?;
+        let last = *self.cache.prices_by_date.keys().next_back()?;
+
+        Some((
+            Utc.timestamp_opt(first, 0).single()?,
+            Utc.timestamp_opt(last, 0).single()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use crate::{caches::candle_prices_cache::CandlePricesCache, models::candle_type::CandleType};
+
+    #[tokio::test]
+    async fn view_reads() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut cache = CandlePricesCache::new(CandleType::Minute);
+
+        cache.update(date, 1.0, 1.0);
+        cache.update(date + Duration::minutes(3) + Duration::seconds(5), 2.0, 1.0);
+
+        let view = cache.view();
+
+        assert_eq!(view.len(), 2);
+        assert!(!view.is_empty());
+        assert_eq!(view.get_latest().unwrap().close, 2.0);
+        assert_eq!(view.time_range(), Some((date, date + Duration::minutes(3))));
+        assert_eq!(view.get_by_date_range(date, date + Duration::minutes(1)).len(), 1);
+    }
+}
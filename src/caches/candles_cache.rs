@@ -1,25 +1,106 @@
-use crate::models::{candle::BidAskCandle, candle_data::CandleData, candle_type::CandleType};
-use ahash::AHashMap;
-use chrono::{DateTime, Utc};
-use compact_str::{ToCompactString};
+use crate::caches::candle_prices_cache::CandlePricesCache;
+use crate::models::{
+    candle::BidAskCandle,
+    candle_data::CandleData,
+    candle_interval::CandleInterval,
+    candle_type::CandleType,
+    consistency_error::{ConsistencyError, ConsistencyIssue},
+    side::{Side, SidedCandle},
+};
+use ahash::{AHashMap, AHashSet};
+use std::{borrow::Cow, collections::hash_map::Entry};
+use chrono::{DateTime, Duration, Utc};
+use compact_str::{CompactString, ToCompactString};
+
+/// Checks every `source` candle lies within a single `target` candle, as `CandlePricesCache::resample` requires
+fn nests_into(source: &CandleType, target: &CandleType) -> bool {
+    CandlePricesCache::new(source.clone()).nests_into(target)
+}
+
+type OnEvict = Box<dyn Fn(&str, CandleType, DateTime<Utc>) + Send + Sync>;
+/// (datetime, instrument, bid, ask, bid_vol, ask_vol)
+pub type Tick = (DateTime<Utc>, String, f64, f64, f64, f64);
+/// (datetime, bid, ask, bid_vol, ask_vol)
+pub type Quote = (DateTime<Utc>, f64, f64, f64, f64);
 
 pub struct CandlesCache {
     candles_by_ids: AHashMap<String, BidAskCandle>,
-    pub candle_types: Vec<CandleType>,
+    instruments: AHashSet<CompactString>,
+    pending_ticks: AHashMap<CompactString, (CandleData, CandleData)>,
+    on_evict: Option<OnEvict>,
+    /// Decimal places prices of an instrument are rounded to on update
+    precisions: AHashMap<CompactString, u32>,
+    /// Start date of the latest candle per (instrument, candle type)
+    latest_dates: AHashMap<(CompactString, CandleType), DateTime<Utc>>,
+    /// Candle types computed on read from their source type instead of being updated per tick
+    derived_types: AHashMap<CandleType, CandleType>,
+    /// Deduplicated and sorted on construction, never changed afterwards
+    candle_types: Vec<CandleType>,
     pub last_update_date: Option<DateTime<Utc>>,
+    /// Limit of tracked instruments: ticks of new instruments beyond it are rejected
+    pub max_instruments: Option<usize>,
 }
 
 impl CandlesCache {
     pub fn new(candle_types: Vec<CandleType>) -> Self {
         let mut candle_types = candle_types;
-        candle_types.dedup();
         candle_types.sort();
+        candle_types.dedup();
 
         Self {
             candles_by_ids: AHashMap::new(),
+            instruments: AHashSet::new(),
+            pending_ticks: AHashMap::new(),
+            on_evict: None,
+            precisions: AHashMap::new(),
+            latest_dates: AHashMap::new(),
+            derived_types: AHashMap::new(),
             candle_types,
             last_update_date: None,
+            max_instruments: None,
+        }
+    }
+
+    /// Builds cache with candles of the ticks
+    pub fn from_ticks(candle_types: Vec<CandleType>, ticks: &[Tick]) -> Self {
+        let mut cache = Self::new(candle_types);
+
+        for (datetime, instrument, bid, ask, bid_vol, ask_vol) in ticks {
+            cache.create_or_update(*datetime, instrument, *bid, *ask, *bid_vol, *ask_vol);
+        }
+
+        cache
+    }
+
+    pub fn get_candle_types(&self) -> &[CandleType] {
+        &self.candle_types
+    }
+
+    /// Makes `derived` candles computed on read by resampling `source` candles rather than updated
+    /// per tick. Stored `derived` candles are dropped. Derived candles are served by range queries only.
+    /// Returns false if a type isn't configured, `source` is derived, `derived` is a source itself
+    /// or `derived` candles aren't unions of whole `source` candles, e.g. months of weeks
+    pub fn derive_from(&mut self, derived: CandleType, source: CandleType) -> bool {
+        let is_configured = self.candle_types.contains(&derived) && self.candle_types.contains(&source);
+
+        if !is_configured
+            || !nests_into(&source, &derived)
+            || self.derived_types.contains_key(&source)
+            || self.derived_types.values().any(|candle_type| *candle_type == derived)
+        {
+            return false;
         }
+
+        self.candles_by_ids.retain(|_id, candle| candle.candle_type != derived);
+        self.latest_dates.retain(|(_instrument, candle_type), _date| *candle_type != derived);
+        self.derived_types.insert(derived, source);
+
+        true
+    }
+
+    /// Sets callback invoked with (instrument, candle_type, datetime) of each evicted candle
+    pub fn set_on_evict(&mut self, on_evict: OnEvict) {
+        self.on_evict = Some(on_evict);
     }
 
     pub fn get_all(&self) -> &AHashMap<String, BidAskCandle> {
@@ -30,11 +111,46 @@ impl CandlesCache {
         self.candles_by_ids.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.candles_by_ids.is_empty()
+    }
+
+    /// Approximates heap memory of candles across instruments and candle types: hash table slots
+    /// of allocated capacity (id `String`, `BidAskCandle` and a control byte) plus id characters.
+    /// Instruments longer than `CompactString` inline capacity add their heap copies, other
+    /// indexes and buffered ticks aren't counted
+    pub fn estimated_bytes(&self) -> usize {
+        let slot_bytes = std::mem::size_of::<String>() + std::mem::size_of::<BidAskCandle>() + 1;
+        let heap_bytes: usize = self
+            .candles_by_ids
+            .iter()
+            .map(|(id, candle)| {
+                let instrument_bytes = if candle.instrument.is_heap_allocated() { candle.instrument.len() } else { 0 };
+
+                id.capacity() + instrument_bytes
+            })
+            .sum();
+
+        self.candles_by_ids.capacity() * slot_bytes + heap_bytes
+    }
+
     pub fn contains(&self, candle_id: &str) -> bool {
         self.candles_by_ids.contains_key(candle_id)
     }
 
-    pub fn insert(&mut self, candle: BidAskCandle) {
+    pub fn contains_many(&self, candle_ids: &[String]) -> Vec<bool> {
+        candle_ids
+            .iter()
+            .map(|id| self.candles_by_ids.contains_key(id))
+            .collect()
+    }
+
+    /// Inserts or overwrites candle. Returns false if instrument is rejected by `max_instruments`
+    pub fn insert(&mut self, candle: BidAskCandle) -> bool {
+        if !self.admit_instrument(&candle.instrument) {
+            return false;
+        }
+
         #[cfg(feature = "console-log")]
         println!(
             "insert candle {}: {} {}; {} total count",
@@ -44,9 +160,67 @@ impl CandlesCache {
             self.candles_by_ids.len() + 1
         );
 
+        track_latest(&mut self.latest_dates, &candle.instrument, &candle.candle_type, candle.datetime);
         self.candles_by_ids.insert(candle.get_id(), candle);
+
+        true
+    }
+
+    /// Inserts candle only if its id isn't cached yet, so repeated loads don't overwrite candles.
+    /// Returns true if candle is inserted, false if it's cached or instrument is rejected by `max_instruments`
+    pub fn insert_if_absent(&mut self, candle: BidAskCandle) -> bool {
+        if self.candles_by_ids.contains_key(&candle.get_id()) {
+            return false;
+        }
+
+        self.insert(candle)
+    }
+
+    pub fn get_instruments(&self) -> &AHashSet<CompactString> {
+        &self.instruments
+    }
+
+    /// Sets decimal places tick prices of the instrument are rounded to by `create_or_update` and `buffer_update`
+    pub fn set_precision(&mut self, instrument: &str, decimals: u32) {
+        self.precisions.insert(instrument.to_compact_string(), decimals);
+    }
+
+    /// Rounds price to the instrument precision, if it's set
+    fn round_price(&self, instrument: &str, price: f64) -> f64 {
+        match self.precisions.get(instrument) {
+            Some(decimals) => {
+                let factor = 10f64.powi(*decimals as i32);
+
+                (price * factor).round() / factor
+            }
+            None => price,
+        }
     }
 
+    /// Registers instrument if it's new and `max_instruments` isn't reached
+    fn admit_instrument(&mut self, instrument: &str) -> bool {
+        if self.instruments.contains(instrument) {
+            return true;
+        }
+
+        if let Some(max_instruments) = self.max_instruments {
+            if self.instruments.len() >= max_instruments {
+                #[cfg(feature = "console-log")]
+                println!(
+                    "reject instrument {}: {} instruments limit is reached",
+                    instrument, max_instruments
+                );
+
+                return false;
+            }
+        }
+
+        self.instruments.insert(instrument.to_compact_string());
+
+        true
+    }
+
+    /// Applies tick to candles of all types. Returns false if instrument is rejected by `max_instruments`
     pub fn create_or_update(
         &mut self,
         datetime: DateTime<Utc>,
@@ -55,8 +229,19 @@ impl CandlesCache {
         ask: f64,
         bid_vol: f64,
         ask_vol: f64,
-    ) {
+    ) -> bool {
+        if !self.admit_instrument(instrument) {
+            return false;
+        }
+
+        let bid = self.round_price(instrument, bid);
+        let ask = self.round_price(instrument, ask);
+
         for candle_type in self.candle_types.iter() {
+            if self.derived_types.contains_key(candle_type) {
+                continue;
+            }
+
             let candle_datetime = candle_type.get_start_date(datetime);
             let id = BidAskCandle::generate_id(instrument, candle_type, candle_datetime);
             let candle = self.candles_by_ids.get_mut(&id);
@@ -73,6 +258,7 @@ impl CandlesCache {
                     self.candles_by_ids.len() + 1
                 );
 
+                track_latest(&mut self.latest_dates, instrument, candle_type, candle_datetime);
                 self.candles_by_ids.insert(
                     id,
                     BidAskCandle {
@@ -87,11 +273,220 @@ impl CandlesCache {
         }
         
         self.last_update_date.replace(Utc::now());
+
+        true
+    }
+
+    /// Applies quotes of one instrument like `create_or_update` called per quote, but looks up
+    /// a candle once per run of consecutive quotes in it, so date sorted batches are cheap.
+    /// Returns false if instrument is rejected by `max_instruments`
+    pub fn create_or_update_many(&mut self, instrument: &str, quotes: &[Quote]) -> bool {
+        if !self.admit_instrument(instrument) {
+            return false;
+        }
+
+        let quotes: Vec<Quote> = quotes
+            .iter()
+            .map(|(datetime, bid, ask, bid_vol, ask_vol)| {
+                (*datetime, self.round_price(instrument, *bid), self.round_price(instrument, *ask), *bid_vol, *ask_vol)
+            })
+            .collect();
+
+        for candle_type in self.candle_types.iter() {
+            if self.derived_types.contains_key(candle_type) {
+                continue;
+            }
+
+            let mut current: Option<&mut BidAskCandle> = None;
+
+            for (datetime, bid, ask, bid_vol, ask_vol) in quotes.iter().copied() {
+                let candle_datetime = candle_type.get_start_date(datetime);
+
+                if let Some(candle) = current.as_deref_mut().filter(|candle| candle.datetime == candle_datetime) {
+                    candle.update(datetime, bid, ask, bid_vol, ask_vol);
+                    continue;
+                }
+
+                let id = BidAskCandle::generate_id(instrument, candle_type, candle_datetime);
+                #[cfg(feature = "console-log")]
+                let total_count = self.candles_by_ids.len() + 1;
+                let candle = match self.candles_by_ids.entry(id) {
+                    Entry::Occupied(entry) => {
+                        let candle = entry.into_mut();
+                        candle.update(datetime, bid, ask, bid_vol, ask_vol);
+
+                        candle
+                    }
+                    Entry::Vacant(entry) => {
+                        #[cfg(feature = "console-log")]
+                        println!(
+                            "create candle {}: {} {}; {} total count",
+                            instrument.to_owned(),
+                            datetime.to_rfc3339(),
+                            entry.key(),
+                            total_count
+                        );
+
+                        track_latest(&mut self.latest_dates, instrument, candle_type, candle_datetime);
+                        entry.insert(BidAskCandle {
+                            ask_data: CandleData::new(datetime, ask, ask_vol),
+                            bid_data: CandleData::new(datetime, bid, bid_vol),
+                            candle_type: candle_type.clone(),
+                            instrument: instrument.to_compact_string(),
+                            datetime: candle_datetime,
+                        })
+                    }
+                };
+
+                current = Some(candle);
+            }
+        }
+
+        if !quotes.is_empty() {
+            self.last_update_date.replace(Utc::now());
+        }
+
+        true
+    }
+
+    /// Buffers tick instead of applying it to every candle type. Buffered ticks of an instrument are
+    /// aggregated into one bid/ask bar, so high/low, volume and open/close aren't lost, and are applied
+    /// by `flush` or when a tick falls into another candle. Candles lag behind the feed until the flush.
+    /// Returns false if instrument is rejected by `max_instruments`
+    pub fn buffer_update(
+        &mut self,
+        datetime: DateTime<Utc>,
+        instrument: &str,
+        bid: f64,
+        ask: f64,
+        bid_vol: f64,
+        ask_vol: f64,
+    ) -> bool {
+        if !self.admit_instrument(instrument) {
+            return false;
+        }
+
+        let bid = self.round_price(instrument, bid);
+        let ask = self.round_price(instrument, ask);
+
+        if let Some((pending_bid, pending_ask)) = self.pending_ticks.get_mut(instrument) {
+            let is_same_candle = self.candle_types.iter().all(|candle_type| {
+                candle_type.get_start_date(pending_bid.open_datetime) == candle_type.get_start_date(datetime)
+            });
+
+            if is_same_candle {
+                pending_bid.update(datetime, bid, bid_vol);
+                pending_ask.update(datetime, ask, ask_vol);
+
+                return true;
+            }
+
+            self.flush_instrument(instrument);
+        }
+
+        self.pending_ticks.insert(
+            instrument.to_compact_string(),
+            (CandleData::new(datetime, bid, bid_vol), CandleData::new(datetime, ask, ask_vol)),
+        );
+
+        true
+    }
+
+    /// Applies buffered ticks of all instruments. Returns count of flushed instruments
+    pub fn flush(&mut self) -> usize {
+        let pending_ticks = std::mem::take(&mut self.pending_ticks);
+        let flushed_count = pending_ticks.len();
+
+        for (instrument, (bid, ask)) in pending_ticks {
+            self.apply_bars(&instrument, &bid, &ask);
+        }
+
+        if flushed_count > 0 {
+            self.last_update_date.replace(Utc::now());
+        }
+
+        flushed_count
+    }
+
+    fn flush_instrument(&mut self, instrument: &str) {
+        if let Some((bid, ask)) = self.pending_ticks.remove(instrument) {
+            self.apply_bars(instrument, &bid, &ask);
+            self.last_update_date.replace(Utc::now());
+        }
+    }
+
+    /// Merges bid/ask bars into candles of all types or creates them
+    fn apply_bars(&mut self, instrument: &str, bid: &CandleData, ask: &CandleData) {
+        for candle_type in self.candle_types.iter() {
+            if self.derived_types.contains_key(candle_type) {
+                continue;
+            }
+
+            let candle_datetime = candle_type.get_start_date(bid.open_datetime);
+            let id = BidAskCandle::generate_id(instrument, candle_type, candle_datetime);
+
+            if let Some(candle) = self.candles_by_ids.get_mut(&id) {
+                candle.bid_data.merge(bid);
+                candle.ask_data.merge(ask);
+            } else {
+                track_latest(&mut self.latest_dates, instrument, candle_type, candle_datetime);
+                self.candles_by_ids.insert(
+                    id,
+                    BidAskCandle {
+                        ask_data: ask.clone(),
+                        bid_data: bid.clone(),
+                        candle_type: candle_type.clone(),
+                        instrument: instrument.to_compact_string(),
+                        datetime: candle_datetime,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Merges pre-aggregated bid/ask bars into the candle of specified type or creates it.
+    /// Returns false if instrument is rejected by `max_instruments` or the type is derived or not cached
+    pub fn ingest_ohlc(
+        &mut self,
+        instrument: &str,
+        candle_type: CandleType,
+        datetime: DateTime<Utc>,
+        bid_ohlcv: CandleData,
+        ask_ohlcv: CandleData,
+    ) -> bool {
+        if self.derived_types.contains_key(&candle_type) || !self.candle_types.contains(&candle_type) {
+            return false;
+        }
+
+        if !self.admit_instrument(instrument) {
+            return false;
+        }
+
+        let candle_datetime = candle_type.get_start_date(datetime);
+        let id = BidAskCandle::generate_id(instrument, &candle_type, candle_datetime);
+
+        if let Some(candle) = self.candles_by_ids.get_mut(&id) {
+            candle.bid_data.merge(&bid_ohlcv);
+            candle.ask_data.merge(&ask_ohlcv);
+        } else {
+            self.insert(BidAskCandle {
+                candle_type,
+                datetime: candle_datetime,
+                instrument: instrument.to_compact_string(),
+                bid_data: bid_ohlcv,
+                ask_data: ask_ohlcv,
+            });
+        }
+
+        self.last_update_date.replace(Utc::now());
+
+        true
     }
 
-    /// Gets candles with date bigger or equals specified date
+    /// Gets candles with date bigger or equals specified date. Candles of types
+    /// the cache isn't configured with are skipped
     pub fn get_after(&self, datetime: DateTime<Utc>) -> Option<Vec<&BidAskCandle>> {
-        if self.candles_by_ids.len() == 0 {
+        if self.candles_by_ids.is_empty() {
             return None;
         }
 
@@ -99,103 +494,1843 @@ impl CandlesCache {
 
         let candles = self
             .candles_by_ids
-            .iter()
-            .filter_map(|(_id, candle)| {
-                let current_date = candle_dates.get(&candle.candle_type).expect("wrong calculate_candle_dates");
-
-                if candle.datetime >= *current_date {
-                    Some(candle)
-                } else {
-                    None
-                }
+            .values()
+            .filter(|candle| {
+                candle_dates
+                    .get(&candle.candle_type)
+                    .is_some_and(|current_date| candle.datetime >= *current_date)
             })
             .collect();
 
         Some(candles)
     }
 
-    /// Removes candles with date less or equals specified date
+    /// Removes candles with date before the candle containing specified date, which `get_after`
+    /// still returns, so a candle is never both kept by it and removed here. Candles are removed
+    /// and reported to `on_evict` in date order, ties ordered by candle type and instrument
     pub fn remove_before(&mut self, datetime: DateTime<Utc>, candle_type: Option<CandleType>) -> i32 {
-        let mut removed_count = 0;
+        let dates = match &candle_type {
+            Some(candle_type) => AHashMap::from_iter([(candle_type.clone(), candle_type.get_start_date(datetime))]),
+            None => self.calculate_candle_dates(datetime),
+        };
+
+        let expired = self
+            .candles_by_ids
+            .iter()
+            .filter(|(_id, candle)| {
+                dates
+                    .get(&candle.candle_type)
+                    .is_some_and(|current_date| candle.datetime < *current_date)
+            })
+            .map(|(id, candle)| (candle.datetime, candle.candle_type.clone(), candle.instrument.clone(), id.clone()))
+            .collect();
+
+        self.remove_expired(expired, true)
+    }
 
-        if let Some(candle_type) = candle_type {
-            self.candles_by_ids.retain(|_id, candle| {
-                let current_date = candle_type.get_start_date(datetime);
+    /// Removes all but the newest `max_per_series` candles of each (instrument, candle type).
+    /// Candles are removed and reported to `on_evict` like in `remove_before`. Returns removed count
+    pub fn remove_keeping_last(&mut self, max_per_series: usize) -> i32 {
+        let mut series: AHashMap<(&str, &CandleType), Vec<_>> = AHashMap::new();
 
-                if candle.datetime <= current_date && candle.candle_type == candle_type {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
-                }
-            });
-        } else {
-            let dates = self.calculate_candle_dates(datetime);
+        for (id, candle) in self.candles_by_ids.iter() {
+            series
+                .entry((candle.instrument.as_str(), &candle.candle_type))
+                .or_default()
+                .push((candle.datetime, id));
+        }
+
+        let mut expired = Vec::new();
+
+        for ((instrument, candle_type), mut candles) in series {
+            if candles.len() <= max_per_series {
+                continue;
+            }
+
+            // newest first, ties by id to stay deterministic
+            candles.sort_by(|a, b| b.cmp(a));
+
+            for (datetime, id) in candles.into_iter().skip(max_per_series) {
+                expired.push((datetime, candle_type.clone(), instrument.to_compact_string(), id.clone()));
+            }
+        }
 
-            self.candles_by_ids.retain(|_id, candle| {
-                let current_date = dates.get(&candle.candle_type).expect("Wrong calculate_candle_dates");
+        self.remove_expired(expired, true)
+    }
+
+    /// Downsamples minute candles of the instrument into hour candles and removes the minutes.
+    /// Only hours ending not later than `older_than` are compacted, so hour data stays complete,
+    /// and hour candles already present are kept as is. Does nothing unless hours are
+    /// a non-derived candle type of the cache. Minutes live on in hours, so they aren't reported
+    /// to `on_evict`. Returns removed minutes count
+    pub fn compact_old(&mut self, instrument: &str, older_than: DateTime<Utc>) -> i32 {
+        if !self.candle_types.contains(&CandleType::Hour) || self.derived_types.contains_key(&CandleType::Hour) {
+            return 0;
+        }
+
+        let hour_cutoff = CandleType::Hour.get_start_date(older_than);
+        let mut minutes: Vec<&BidAskCandle> = self
+            .candles_by_ids
+            .values()
+            .filter(|candle| {
+                candle.candle_type == CandleType::Minute
+                    && candle.instrument == instrument
+                    && candle.datetime < hour_cutoff
+            })
+            .collect();
+        minutes.sort_by_key(|candle| candle.datetime);
+
+        let mut hours: Vec<BidAskCandle> = Vec::new();
 
-                if candle.datetime <= *current_date {
-                    removed_count += 1;
-                    false
-                } else {
-                    true
+        for minute in minutes.iter() {
+            let hour_datetime = CandleType::Hour.get_start_date(minute.datetime);
+
+            match hours.last_mut() {
+                Some(hour) if hour.datetime == hour_datetime => {
+                    hour.bid_data.merge(&minute.bid_data);
+                    hour.ask_data.merge(&minute.ask_data);
                 }
-            });
+                _ => hours.push(BidAskCandle {
+                    candle_type: CandleType::Hour,
+                    datetime: hour_datetime,
+                    instrument: minute.instrument.clone(),
+                    bid_data: minute.bid_data.clone(),
+                    ask_data: minute.ask_data.clone(),
+                }),
+            }
+        }
+
+        let expired = minutes
+            .iter()
+            .map(|candle| (candle.datetime, CandleType::Minute, candle.instrument.clone(), candle.get_id()))
+            .collect();
+
+        for hour in hours {
+            if !self.candles_by_ids.contains_key(&hour.get_id()) {
+                self.insert(hour);
+            }
         }
 
-        removed_count
+        self.remove_expired(expired, false)
     }
 
-    pub fn get(&self, id: &str) -> Option<&BidAskCandle> {
-        self.candles_by_ids.get(id)
+    /// Removes candles with date before `cutoff` and returns their data by (instrument, candle type, side),
+    /// each series in date order. Extracted candles are handed over, so they aren't reported to `on_evict`
+    pub fn extract_before_all(&mut self, cutoff: DateTime<Utc>) -> AHashMap<(String, CandleType, Side), Vec<CandleData>> {
+        let mut expired: Vec<_> = self
+            .candles_by_ids
+            .iter()
+            .filter(|(_id, candle)| candle.datetime < cutoff)
+            .map(|(id, candle)| (candle.datetime, id.clone()))
+            .collect();
+        expired.sort();
+
+        let mut extracted: AHashMap<(String, CandleType, Side), Vec<CandleData>> = AHashMap::new();
+
+        for (_datetime, id) in expired {
+            let Some(candle) = self.candles_by_ids.remove(&id) else {
+                continue;
+            };
+
+            self.forget_latest(&candle.instrument, &candle.candle_type, candle.datetime);
+
+            for (side, data) in [(Side::Bid, candle.bid_data), (Side::Ask, candle.ask_data)] {
+                extracted
+                    .entry((candle.instrument.to_string(), candle.candle_type.clone(), side))
+                    .or_default()
+                    .push(data);
+            }
+        }
+
+        if !extracted.is_empty() {
+            self.forget_instruments_without_candles();
+        }
+
+        extracted
     }
 
-    fn calculate_candle_dates(&self, datetime: DateTime<Utc>) -> AHashMap<CandleType, DateTime<Utc>> {
-        let mut dates = AHashMap::with_capacity(self.candle_types.len());
+    /// Drops latest date index entry of (instrument, candle type) if it points to the removed candle
+    fn forget_latest(&mut self, instrument: &CompactString, candle_type: &CandleType, datetime: DateTime<Utc>) {
+        let latest_key = (instrument.clone(), candle_type.clone());
 
-        for candle_type in self.candle_types.iter() {
-            dates.insert(candle_type.to_owned(), candle_type.get_start_date(datetime));
+        if self.latest_dates.get(&latest_key) == Some(&datetime) {
+            self.latest_dates.remove(&latest_key);
         }
+    }
 
-        dates
+    /// Removes candles given as (datetime, candle_type, instrument, id) in that order,
+    /// reporting them to `on_evict` if `report` is set
+    fn remove_expired(&mut self, mut expired: Vec<(DateTime<Utc>, CandleType, CompactString, String)>, report: bool) -> i32 {
+        expired.sort();
+
+        for (datetime, candle_type, instrument, id) in expired.iter() {
+            self.candles_by_ids.remove(id);
+            self.forget_latest(instrument, candle_type, *datetime);
+
+            #[cfg(feature = "console-log")]
+            println!("remove candle {}: {} {}", instrument, datetime.to_rfc3339(), id);
+
+            if let Some(on_evict) = self.on_evict.as_ref().filter(|_on_evict| report) {
+                on_evict(instrument, candle_type.clone(), *datetime);
+            }
+        }
+
+        if !expired.is_empty() {
+            self.forget_instruments_without_candles();
+        }
+
+        expired.len() as i32
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::models::candle_type::CandleType;
-    use chrono::{DateTime, TimeZone, Utc};
-    use crate::caches::candles_cache::CandlesCache;
+    /// Unregisters instruments left without candles and buffered ticks, so they don't count
+    /// towards `max_instruments` anymore
+    fn forget_instruments_without_candles(&mut self) {
+        let instruments_with_candles: AHashSet<&CompactString> =
+            self.candles_by_ids.values().map(|candle| &candle.instrument).collect();
 
-    #[tokio::test]
-    async fn calculate_candle_dates() {
-        let candle_types = [
-            CandleType::Minute,
-            CandleType::ThreeMinutes,
-            CandleType::FiveMinutes,
-            CandleType::FifteenMinutes,
-            CandleType::ThirtyMinutes,
-            CandleType::Hour,
-            CandleType::TwoHours,
-            CandleType::FourHours,
-            CandleType::SixHours,
-            CandleType::EightHours,
-            CandleType::TwelveHours,
-            CandleType::Day,
-            CandleType::ThreeDays,
-            CandleType::SevenDays,
-            CandleType::Month,
-        ];
-        let cache = CandlesCache::new(candle_types.to_vec());
-        let initial_date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
-        let dates = cache.calculate_candle_dates(initial_date);
+        self.instruments.retain(|instrument| {
+            instruments_with_candles.contains(instrument) || self.pending_ticks.contains_key(instrument)
+        });
+    }
 
-        assert_eq!(candle_types.len(), dates.len());
+    /// Gets mid price ((bid close + ask close) / 2) of the latest candle per instrument
+    pub fn all_latest_mid(&self, candle_type: CandleType) -> AHashMap<String, f64> {
+        let mut latest: AHashMap<&str, &BidAskCandle> = AHashMap::new();
 
-        for candle_type in candle_types.iter() {
-            let date = dates.get(&candle_type);
-            assert_eq!(date, Some(&candle_type.get_start_date(initial_date)))
+        for candle in self.candles_by_ids.values() {
+            if candle.candle_type != candle_type {
+                continue;
+            }
+
+            let current = latest.entry(candle.instrument.as_str()).or_insert(candle);
+
+            if candle.datetime > current.datetime {
+                *current = candle;
+            }
         }
+
+        latest
+            .into_iter()
+            .map(|(instrument, candle)| {
+                let mid = (candle.bid_data.close + candle.ask_data.close) / 2.0;
+
+                (instrument.to_string(), mid)
+            })
+            .collect()
+    }
+
+    /// Gets the instrument candle of the type with the greatest date. O(1) by the latest dates index
+    pub fn get_latest(&self, instrument: &str, candle_type: &CandleType) -> Option<&BidAskCandle> {
+        let date = self
+            .latest_dates
+            .get(&(instrument.to_compact_string(), candle_type.clone()))?;
+
+        self.get(&BidAskCandle::generate_id(instrument, candle_type, *date))
+    }
+
+    /// Gets side close of the latest instrument candle of the finest maintained candle type
+    pub fn last_price(&self, instrument: &str, side: Side) -> Option<f64> {
+        let candle_type = self
+            .candle_types
+            .iter()
+            .filter(|candle_type| !self.derived_types.contains_key(candle_type))
+            .min_by_key(|candle_type| candle_type.get_duration(DateTime::<Utc>::default()))?;
+
+        self.get_latest(instrument, candle_type)
+            .map(|candle| candle.get_data(side).close)
+    }
+
+    /// Gets start dates of the first and the last instrument candles of the type. Scans all candles
+    pub fn time_range(&self, instrument: &str, candle_type: &CandleType) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        self.candles_by_ids
+            .values()
+            .filter(|candle| candle.candle_type == *candle_type && candle.instrument == instrument)
+            .fold(None, |range, candle| match range {
+                None => Some((candle.datetime, candle.datetime)),
+                Some((first, last)) => Some((first.min(candle.datetime), last.max(candle.datetime))),
+            })
+    }
+
+    /// Gets intersection of the instruments `time_range`s, None if they are disjoint.
+    /// Bid and ask share candles, so it's the same for both sides
+    pub fn common_range(
+        &self,
+        instrument_a: &str,
+        instrument_b: &str,
+        candle_type: &CandleType,
+    ) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let (first_a, last_a) = self.time_range(instrument_a, candle_type)?;
+        let (first_b, last_b) = self.time_range(instrument_b, candle_type)?;
+        let first = first_a.max(first_b);
+        let last = last_a.min(last_b);
+
+        if first > last {
+            return None;
+        }
+
+        Some((first, last))
+    }
+
+    /// Gets Pearson correlation of side closes of two instruments over candles of [datetime_from, datetime_to)
+    /// both have. None if there are less than two such candles or a series has zero variance
+    pub fn correlation(
+        &self,
+        instrument_a: &str,
+        instrument_b: &str,
+        candle_type: &CandleType,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Option<f64> {
+        let closes_b: AHashMap<DateTime<Utc>, f64> = self
+            .get_range(instrument_b, candle_type, datetime_from, datetime_to)
+            .into_iter()
+            .map(|candle| (candle.datetime, candle.get_data(side).close))
+            .collect();
+        let pairs: Vec<(f64, f64)> = self
+            .get_range(instrument_a, candle_type, datetime_from, datetime_to)
+            .into_iter()
+            .filter_map(|candle| Some((candle.get_data(side).close, *closes_b.get(&candle.datetime)?)))
+            .collect();
+
+        if pairs.len() < 2 {
+            return None;
+        }
+
+        let count = pairs.len() as f64;
+        let mean_a = pairs.iter().map(|(a, _b)| a).sum::<f64>() / count;
+        let mean_b = pairs.iter().map(|(_a, b)| b).sum::<f64>() / count;
+        let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+
+        for (a, b) in pairs {
+            covariance += (a - mean_a) * (b - mean_b);
+            variance_a += (a - mean_a).powi(2);
+            variance_b += (b - mean_b).powi(2);
+        }
+
+        if variance_a == 0.0 || variance_b == 0.0 {
+            return None;
+        }
+
+        Some(covariance / (variance_a * variance_b).sqrt())
+    }
+
+    /// Gets count of stored candles of the instrument per candle type, zero for types without candles.
+    /// Bid and ask share candles, so counts are the same for both sides. Derived types aren't stored
+    /// and aren't included
+    pub fn candle_counts(&self, instrument: &str) -> AHashMap<CandleType, usize> {
+        let mut counts: AHashMap<CandleType, usize> = self
+            .candle_types
+            .iter()
+            .filter(|candle_type| !self.derived_types.contains_key(candle_type))
+            .map(|candle_type| (candle_type.clone(), 0))
+            .collect();
+
+        for candle in self.candles_by_ids.values().filter(|candle| candle.instrument == instrument) {
+            if let Some(count) = counts.get_mut(&candle.candle_type) {
+                *count += 1;
+            }
+        }
+
+        counts
+    }
+
+    /// Gets sorted instruments having candles of the type with start date in [datetime_from, datetime_to).
+    /// Unlike `get_instruments`, skips instruments tracked but without candles in the window
+    pub fn active_instruments(
+        &self,
+        candle_type: CandleType,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<String> {
+        let instruments: AHashSet<&str> = self
+            .candles_by_ids
+            .values()
+            .filter(|candle| {
+                candle.candle_type == candle_type
+                    && candle.datetime >= datetime_from
+                    && candle.datetime < datetime_to
+            })
+            .map(|candle| candle.instrument.as_str())
+            .collect();
+        let mut instruments: Vec<String> = instruments.into_iter().map(|instrument| instrument.to_string()).collect();
+        instruments.sort();
+
+        instruments
+    }
+
+    /// Gets candles of the date range using the finest candle type available for each part of it.
+    /// Result is ordered from the oldest part to the newest one. The coarser candle containing
+    /// a switch point is included, so it may overlap the first finer candles. Scans all candles per type
+    pub fn get_best_available(
+        &self,
+        instrument: &str,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<(CandleType, Vec<CandleData>)> {
+        let mut candle_types = self.candle_types.clone();
+        candle_types.sort_by_key(|candle_type| candle_type.get_duration(datetime_from));
+
+        let mut result = Vec::new();
+        let mut date_to = datetime_to;
+
+        for candle_type in candle_types {
+            if date_to <= datetime_from {
+                break;
+            }
+
+            let date_from = candle_type.get_start_date(datetime_from);
+            let mut candles: Vec<&BidAskCandle> = self
+                .candles_by_ids
+                .values()
+                .filter(|candle| {
+                    candle.instrument == instrument
+                        && candle.candle_type == candle_type
+                        && candle.datetime >= date_from
+                        && candle.datetime < date_to
+                })
+                .collect();
+
+            if candles.is_empty() {
+                continue;
+            }
+
+            candles.sort_by_key(|candle| candle.datetime);
+            date_to = if candles[0].datetime <= date_from {
+                datetime_from
+            } else {
+                candles[0].datetime
+            };
+
+            let candles = candles
+                .into_iter()
+                .map(|candle| candle.get_data(side).clone())
+                .collect();
+            result.push((candle_type, candles));
+        }
+
+        result.reverse();
+
+        result
+    }
+
+    /// Builds weighted-average OHLC index candles from (instrument, weight) components for candle
+    /// start dates in [datetime_from, datetime_to). Dates missing from any component are skipped.
+    /// Volume is the sum of component volumes
+    pub fn build_index(
+        &self,
+        components: &[(String, f64)],
+        candle_type: CandleType,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> CandlePricesCache {
+        let mut index = CandlePricesCache::new(candle_type.clone());
+        let total_weight: f64 = components.iter().map(|(_instrument, weight)| weight).sum();
+
+        if total_weight == 0.0 {
+            return index;
+        }
+
+        let component_candles: Vec<AHashMap<DateTime<Utc>, CandleData>> = components
+            .iter()
+            .map(|(instrument, _weight)| {
+                self.get_range(instrument, &candle_type, datetime_from, datetime_to)
+                    .into_iter()
+                    .map(|candle| (candle.datetime, candle.get_data(side).clone()))
+                    .collect()
+            })
+            .collect();
+
+        for date in candle_type.iter_start_dates(datetime_from, datetime_to) {
+            let candles: Option<Vec<&CandleData>> = component_candles
+                .iter()
+                .map(|candles| candles.get(&date))
+                .collect();
+            let Some(candles) = candles else {
+                continue;
+            };
+            let weighted = |price: fn(&CandleData) -> f64| -> f64 {
+                candles
+                    .iter()
+                    .zip(components.iter())
+                    .map(|(candle, (_instrument, weight))| price(candle) * weight)
+                    .sum::<f64>()
+                    / total_weight
+            };
+
+            index.prices_by_date.insert(
+                date.timestamp(),
+                CandleData {
+                    open: weighted(|candle| candle.open),
+                    close: weighted(|candle| candle.close),
+                    high: weighted(|candle| candle.high),
+                    low: weighted(|candle| candle.low),
+                    datetime: candles.iter().map(|candle| candle.datetime).max().unwrap_or(date),
+                    volume: candles.iter().map(|candle| candle.volume).sum(),
+                    tick_count: candles.iter().map(|candle| candle.tick_count).sum(),
+                    open_datetime: date,
+                },
+            );
+        }
+
+        index
+    }
+
+    /// Builds cache of `target_type` candles resampled from `source_type` candles of every instrument.
+    /// Both sides are projected, since they share candles. None unless each `target_type` candle
+    /// is a union of whole `source_type` candles, like `CandlePricesCache::resample`
+    pub fn project(&self, source_type: &CandleType, target_type: CandleType) -> Option<CandlesCache> {
+        if !nests_into(source_type, &target_type) {
+            return None;
+        }
+
+        let mut projected = CandlesCache::new(vec![target_type.clone()]);
+        let mut candles: Vec<&BidAskCandle> = self
+            .candles_by_ids
+            .values()
+            .filter(|candle| candle.candle_type == *source_type)
+            .collect();
+        candles.sort_by_key(|candle| candle.datetime);
+
+        for candle in candles {
+            let candle_datetime = target_type.get_start_date(candle.datetime);
+            let id = BidAskCandle::generate_id(&candle.instrument, &target_type, candle_datetime);
+
+            if let Some(target) = projected.candles_by_ids.get_mut(&id) {
+                target.bid_data.merge(&candle.bid_data);
+                target.ask_data.merge(&candle.ask_data);
+            } else {
+                projected.insert(BidAskCandle {
+                    candle_type: target_type.clone(),
+                    datetime: candle_datetime,
+                    instrument: candle.instrument.clone(),
+                    bid_data: candle.bid_data.clone(),
+                    ask_data: candle.ask_data.clone(),
+                });
+            }
+        }
+
+        Some(projected)
+    }
+
+    /// Cross-checks each candle against finer candles fully inside its interval: its high
+    /// must not be below their highs and its low must not be above their lows
+    pub fn validate_consistency(&self) -> Vec<ConsistencyError> {
+        let mut errors = Vec::new();
+
+        for candle in self.candles_by_ids.values() {
+            let end_date = candle.candle_type.get_end_date(candle.datetime);
+            let duration = candle.candle_type.get_duration(candle.datetime);
+
+            for inner_type in self.candle_types.iter() {
+                if inner_type.get_duration(candle.datetime) >= duration {
+                    continue;
+                }
+
+                for inner in self.get_range(&candle.instrument, inner_type, candle.datetime, end_date) {
+                    if inner_type.get_end_date(inner.datetime) > end_date {
+                        continue;
+                    }
+
+                    for side in [Side::Bid, Side::Ask] {
+                        let data = candle.get_data(side);
+                        let inner_data = inner.get_data(side);
+                        let mut issues = Vec::new();
+
+                        if data.high < inner_data.high {
+                            issues.push(ConsistencyIssue::HighBelowInner);
+                        }
+
+                        if data.low > inner_data.low {
+                            issues.push(ConsistencyIssue::LowAboveInner);
+                        }
+
+                        for issue in issues {
+                            errors.push(ConsistencyError {
+                                instrument: candle.instrument.to_string(),
+                                side,
+                                candle_type: candle.candle_type.clone(),
+                                datetime: candle.datetime,
+                                inner_candle_type: inner_type.clone(),
+                                inner_datetime: inner.datetime,
+                                issue,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        errors
+    }
+
+    /// Gets side data of instrument candles with start date in [datetime_from, datetime_to), labeled with the side
+    pub fn get_by_date_range_labeled(
+        &self,
+        instrument: &str,
+        candle_type: &CandleType,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<SidedCandle> {
+        self.get_range(instrument, candle_type, datetime_from, datetime_to)
+            .into_iter()
+            .map(|candle| SidedCandle {
+                side,
+                data: candle.get_data(side).clone(),
+            })
+            .collect()
+    }
+
+    /// Gets mid price ((bid + ask) / 2) OHLC of instrument candles with start date in [datetime_from, datetime_to).
+    /// Both sides are stored in one candle, so a bucket never has only one of them. Volume is the sum of sides
+    pub fn get_mid_by_date_range(
+        &self,
+        instrument: &str,
+        candle_type: &CandleType,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<CandleData> {
+        self.get_range(instrument, candle_type, datetime_from, datetime_to)
+            .into_iter()
+            .map(|candle| {
+                let (bid, ask) = (&candle.bid_data, &candle.ask_data);
+
+                CandleData {
+                    open: (bid.open + ask.open) / 2.0,
+                    close: (bid.close + ask.close) / 2.0,
+                    high: (bid.high + ask.high) / 2.0,
+                    low: (bid.low + ask.low) / 2.0,
+                    datetime: bid.datetime.max(ask.datetime),
+                    volume: bid.volume + ask.volume,
+                    tick_count: bid.tick_count.max(ask.tick_count),
+                    open_datetime: bid.open_datetime.min(ask.open_datetime),
+                }
+            })
+            .collect()
+    }
+
+    /// Gets side data of instrument candles with start date in [datetime_from, datetime_to],
+    /// so a candle starting exactly at `datetime_to` is included
+    pub fn get_by_date_range_inclusive(
+        &self,
+        instrument: &str,
+        candle_type: &CandleType,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<CandleData> {
+        if datetime_from > datetime_to {
+            return Vec::new();
+        }
+
+        self.get_range(instrument, candle_type, datetime_from, candle_type.get_end_date(datetime_to))
+            .into_iter()
+            .map(|candle| candle.get_data(side).clone())
+            .collect()
+    }
+
+    /// Gets side data of instrument candles with start date in [datetime_from, datetime_to) for each of the candle types
+    pub fn get_multi_timeframe(
+        &self,
+        instrument: &str,
+        side: Side,
+        candle_types: &[CandleType],
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> AHashMap<CandleType, Vec<CandleData>> {
+        candle_types
+            .iter()
+            .map(|candle_type| {
+                let candles = self
+                    .get_range(instrument, candle_type, datetime_from, datetime_to)
+                    .into_iter()
+                    .map(|candle| candle.get_data(side).clone())
+                    .collect();
+
+                (candle_type.clone(), candles)
+            })
+            .collect()
+    }
+
+    /// Gets side data of instrument candles of an arbitrary interval with start date in [datetime_from, datetime_to),
+    /// merged from candles of the coarsest cached type dividing the interval. The last candle isn't cut at `datetime_to`.
+    /// None if no cached type divides it, e.g. 10m interval with only hour candles
+    pub fn get_interval_candles(
+        &self,
+        instrument: &str,
+        interval: &CandleInterval,
+        side: Side,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Option<Vec<CandleData>> {
+        let source_type = self
+            .candle_types
+            .iter()
+            .filter(|candle_type| !self.derived_types.contains_key(*candle_type))
+            .filter_map(|candle_type| Some((candle_type, CandleInterval::from_candle_type(candle_type)?)))
+            .filter(|(_candle_type, source)| interval.is_multiple_of(source))
+            .max_by_key(|(_candle_type, source)| *source)
+            .map(|(candle_type, _source)| candle_type)?;
+        let mut candles: Vec<(DateTime<Utc>, CandleData)> = Vec::new();
+        let sources_from = interval.get_start_date(datetime_from);
+        let sources_to = interval.get_end_date(datetime_to - Duration::nanoseconds(1));
+
+        for source in self.get_range(instrument, source_type, sources_from, sources_to) {
+            let date = interval.get_start_date(source.datetime);
+
+            if date < datetime_from || date >= datetime_to {
+                continue;
+            }
+
+            match candles.last_mut() {
+                Some((last_date, candle)) if *last_date == date => candle.merge(source.get_data(side)),
+                _ => candles.push((date, source.get_data(side).clone())),
+            }
+        }
+
+        Some(candles.into_iter().map(|(_date, candle)| candle).collect())
+    }
+
+    /// Gets side data of instrument candles with start date not earlier than `now - duration`
+    /// up to the candle containing `now`
+    pub fn get_recent(
+        &self,
+        instrument: &str,
+        candle_type: &CandleType,
+        side: Side,
+        duration: Duration,
+        now: DateTime<Utc>,
+    ) -> Vec<CandleData> {
+        self.get_range(instrument, candle_type, now - duration, candle_type.get_end_date(now))
+            .into_iter()
+            .map(|candle| candle.get_data(side).clone())
+            .collect()
+    }
+
+    /// Gets instrument candles with start date in [datetime_from, datetime_to) ordered by date.
+    /// Candles of derived types are resampled from their source candles
+    fn get_range(
+        &self,
+        instrument: &str,
+        candle_type: &CandleType,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<Cow<'_, BidAskCandle>> {
+        let dates = candle_type
+            .get_start_dates_ordered(datetime_from, datetime_to)
+            .into_iter()
+            .filter(|date| *date >= datetime_from && *date < datetime_to);
+
+        let Some(source_type) = self.derived_types.get(candle_type) else {
+            return dates
+                .filter_map(|date| self.get(&BidAskCandle::generate_id(instrument, candle_type, date)))
+                .map(Cow::Borrowed)
+                .collect();
+        };
+
+        dates
+            .filter_map(|date| {
+                let mut sources = self
+                    .get_range(instrument, source_type, date, candle_type.get_end_date(date))
+                    .into_iter();
+                let mut candle = sources.next()?.into_owned();
+                candle.candle_type = candle_type.clone();
+                candle.datetime = date;
+
+                for source in sources {
+                    candle.bid_data.merge(&source.bid_data);
+                    candle.ask_data.merge(&source.ask_data);
+                }
+
+                Some(Cow::Owned(candle))
+            })
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&BidAskCandle> {
+        self.candles_by_ids.get(id)
+    }
+
+    fn calculate_candle_dates(&self, datetime: DateTime<Utc>) -> AHashMap<CandleType, DateTime<Utc>> {
+        let mut dates = AHashMap::with_capacity(self.candle_types.len());
+
+        for candle_type in self.candle_types.iter() {
+            dates.insert(candle_type.to_owned(), candle_type.get_start_date(datetime));
+        }
+
+        dates
+    }
+}
+
+/// Keeps the latest candle date of (instrument, candle type) up to date with a new candle
+fn track_latest(
+    latest_dates: &mut AHashMap<(CompactString, CandleType), DateTime<Utc>>,
+    instrument: &str,
+    candle_type: &CandleType,
+    datetime: DateTime<Utc>,
+) {
+    let latest = latest_dates
+        .entry((instrument.to_compact_string(), candle_type.clone()))
+        .or_insert(datetime);
+
+    if *latest < datetime {
+        *latest = datetime;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::candle::BidAskCandle;
+    use crate::models::consistency_error::ConsistencyIssue;
+    use crate::models::candle_data::CandleData;
+    use crate::models::candle_interval::CandleInterval;
+    use crate::models::candle_type::CandleType;
+    use crate::models::side::Side;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+    use std::sync::{Arc, Mutex};
+    use crate::caches::candles_cache::{CandlesCache, Quote, Tick};
+
+    #[tokio::test]
+    async fn calculate_candle_dates() {
+        let candle_types = [
+            CandleType::Minute,
+            CandleType::ThreeMinutes,
+            CandleType::FiveMinutes,
+            CandleType::FifteenMinutes,
+            CandleType::ThirtyMinutes,
+            CandleType::Hour,
+            CandleType::TwoHours,
+            CandleType::FourHours,
+            CandleType::SixHours,
+            CandleType::EightHours,
+            CandleType::TwelveHours,
+            CandleType::Day,
+            CandleType::ThreeDays,
+            CandleType::SevenDays,
+            CandleType::Month,
+        ];
+        let cache = CandlesCache::new(candle_types.to_vec());
+        let initial_date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let dates = cache.calculate_candle_dates(initial_date);
+
+        assert_eq!(candle_types.len(), dates.len());
+
+        for candle_type in candle_types.iter() {
+            let date = dates.get(candle_type);
+            assert_eq!(date, Some(&candle_type.get_start_date(initial_date)))
+        }
+    }
+
+    #[tokio::test]
+    async fn all_latest_mid() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(1), "EURUSD", 1.25, 1.75, 1.0, 1.0);
+        cache.create_or_update(date, "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+
+        let mids = cache.all_latest_mid(CandleType::Minute);
+
+        assert_eq!(mids.len(), 2);
+        assert_eq!(mids.get("EURUSD"), Some(&1.5));
+        assert_eq!(mids.get("BTCUSD"), Some(&101.0));
+    }
+
+    #[tokio::test]
+    async fn ingest_ohlc() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let bid = CandleData {
+            open: 1.5,
+            close: 1.25,
+            high: 2.0,
+            low: 1.0,
+            datetime: date + Duration::seconds(59),
+            volume: 10.0,
+            tick_count: 10,
+            open_datetime: date,
+        };
+        let ask = CandleData {
+            open: 1.75,
+            close: 1.5,
+            high: 2.25,
+            low: 1.25,
+            datetime: date + Duration::seconds(59),
+            volume: 12.0,
+            tick_count: 12,
+            open_datetime: date,
+        };
+
+        cache.ingest_ohlc("EURUSD", CandleType::Minute, date, bid, ask);
+
+        let id = BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date);
+        let candle = cache.get(&id).unwrap();
+        assert_eq!(candle.datetime, date);
+        assert_eq!(
+            (candle.bid_data.open, candle.bid_data.high, candle.bid_data.low, candle.bid_data.close, candle.bid_data.volume),
+            (1.5, 2.0, 1.0, 1.25, 10.0)
+        );
+        assert_eq!(
+            (candle.ask_data.open, candle.ask_data.high, candle.ask_data.low, candle.ask_data.close, candle.ask_data.volume),
+            (1.75, 2.25, 1.25, 1.5, 12.0)
+        );
+
+        let next_bar = CandleData::new(date + Duration::seconds(59), 3.0, 1.0);
+        cache.ingest_ohlc("EURUSD", CandleType::Minute, date, next_bar.clone(), next_bar);
+
+        let candle = cache.get(&id).unwrap();
+        assert_eq!(
+            (candle.bid_data.open, candle.bid_data.high, candle.bid_data.low, candle.bid_data.close, candle.bid_data.volume),
+            (1.5, 3.0, 1.0, 3.0, 11.0)
+        );
+
+        let hour_bar = CandleData::new(date, 3.0, 1.0);
+        assert!(!cache.ingest_ohlc("EURUSD", CandleType::Hour, date, hour_bar.clone(), hour_bar));
+        assert!(cache.get(&BidAskCandle::generate_id("EURUSD", &CandleType::Hour, date)).is_none());
+    }
+
+    #[tokio::test]
+    async fn contains_many() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(2), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+
+        let ids = vec![
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date),
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(1)),
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(2)),
+            BidAskCandle::generate_id("BTCUSD", &CandleType::Minute, date),
+        ];
+
+        assert_eq!(cache.contains_many(&ids), vec![true, false, true, false]);
+    }
+
+    #[tokio::test]
+    async fn get_best_available() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 10, 0, 0).unwrap();
+
+        for i in 0..180 {
+            cache.create_or_update(from + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 1.0, 1.0, 1.0);
+        }
+
+        cache.remove_before(from + Duration::minutes(90), Some(CandleType::Minute));
+
+        let result = cache.get_best_available("EURUSD", Side::Ask, from, from + Duration::hours(3));
+
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[0].0, CandleType::Hour);
+        assert_eq!(result[0].1.len(), 2);
+        assert_eq!(result[0].1[0].open, 1.0);
+        assert_eq!(result[1].0, CandleType::Minute);
+        assert_eq!(result[1].1.len(), 90);
+        assert_eq!(result[1].1[0].open, 91.0);
+    }
+
+    #[tokio::test]
+    async fn max_instruments() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        cache.max_instruments = Some(2);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        assert!(cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0));
+        assert!(cache.create_or_update(date, "BTCUSD", 1.0, 1.2, 1.0, 1.0));
+        assert!(!cache.create_or_update(date, "PHANTOM", 1.0, 1.2, 1.0, 1.0));
+        assert!(cache.create_or_update(date + Duration::minutes(1), "EURUSD", 1.0, 1.2, 1.0, 1.0));
+
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get_instruments().len(), 2);
+        assert!(!cache.contains(&BidAskCandle::generate_id("PHANTOM", &CandleType::Minute, date)));
+    }
+
+    #[tokio::test]
+    async fn on_evict() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let evicted_by_callback = evicted.clone();
+        cache.set_on_evict(Box::new(move |instrument, candle_type, datetime| {
+            evicted_by_callback
+                .lock()
+                .unwrap()
+                .push((instrument.to_string(), candle_type, datetime));
+        }));
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..5 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        }
+
+        let removed_count = cache.remove_before(date + Duration::minutes(3), None);
+        let evicted = evicted.lock().unwrap();
+
+        assert_eq!(removed_count, 3);
+        assert_eq!(evicted.len(), 3);
+        assert!(!evicted.contains(&("EURUSD".to_string(), CandleType::Hour, date)));
+        assert!(evicted.contains(&("EURUSD".to_string(), CandleType::Minute, date + Duration::minutes(2))));
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_labeled() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..5 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 0.5, 1.0, 1.0);
+        }
+
+        let candles = cache.get_by_date_range_labeled(
+            "EURUSD",
+            &CandleType::Minute,
+            Side::Bid,
+            date + Duration::minutes(1),
+            date + Duration::minutes(4),
+        );
+
+        assert_eq!(candles.len(), 3);
+        assert!(candles.iter().all(|candle| candle.side == Side::Bid));
+        assert_eq!(candles[0].data.close, 1.0);
+        assert_eq!(candles[2].data.close, 3.0);
+    }
+
+    #[tokio::test]
+    async fn buffer_update() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let mut expected = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let ticks = [(0, 2.0), (10, 5.0), (20, 1.0), (30, 3.0), (70, 4.0), (80, 0.5)];
+
+        for (seconds, price) in ticks {
+            let datetime = date + Duration::seconds(seconds);
+            cache.buffer_update(datetime, "EURUSD", price, price + 1.0, 1.0, 2.0);
+            expected.create_or_update(datetime, "EURUSD", price, price + 1.0, 1.0, 2.0);
+        }
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.flush(), 1);
+        assert_eq!(cache.flush(), 0);
+        assert_eq!(cache.len(), expected.len());
+
+        for (id, expected_candle) in expected.get_all() {
+            let candle = cache.get(id).unwrap();
+
+            for side in [Side::Bid, Side::Ask] {
+                let data = candle.get_data(side);
+                let expected_data = expected_candle.get_data(side);
+
+                assert_eq!(
+                    (data.open, data.high, data.low, data.close, data.volume),
+                    (expected_data.open, expected_data.high, expected_data.low, expected_data.close, expected_data.volume)
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn validate_consistency() {
+        let mut cache = CandlesCache::new(vec![CandleType::Hour, CandleType::Day]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..24 {
+            cache.create_or_update(date + Duration::hours(i), "EURUSD", 1.0 + i as f64, 2.0 + i as f64, 1.0, 1.0);
+        }
+
+        assert!(cache.validate_consistency().is_empty());
+
+        let hour_id = BidAskCandle::generate_id("EURUSD", &CandleType::Hour, date + Duration::hours(5));
+        let mut hour = cache.get(&hour_id).unwrap().clone();
+        hour.bid_data.high = 100.0;
+        cache.insert(hour);
+
+        let errors = cache.validate_consistency();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].candle_type, CandleType::Day);
+        assert_eq!(errors[0].datetime, date);
+        assert_eq!(errors[0].inner_candle_type, CandleType::Hour);
+        assert_eq!(errors[0].inner_datetime, date + Duration::hours(5));
+        assert_eq!(errors[0].side, Side::Bid);
+        assert_eq!(errors[0].issue, ConsistencyIssue::HighBelowInner);
+    }
+
+    #[tokio::test]
+    async fn candle_types_are_processed_once() {
+        let mut cache = CandlesCache::new(vec![CandleType::Hour, CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 2.0);
+        cache.create_or_update(date + Duration::seconds(10), "EURUSD", 1.1, 1.3, 1.0, 2.0);
+
+        let hour = cache.get(&BidAskCandle::generate_id("EURUSD", &CandleType::Hour, date)).unwrap();
+        let minute = cache.get(&BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date)).unwrap();
+
+        assert_eq!(cache.get_candle_types(), &[CandleType::Minute, CandleType::Hour]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!((hour.bid_data.volume, hour.ask_data.volume), (2.0, 4.0));
+        assert_eq!((minute.bid_data.volume, minute.ask_data.volume), (2.0, 4.0));
+    }
+
+    #[tokio::test]
+    async fn build_index() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..3 {
+            let datetime = date + Duration::minutes(i);
+            cache.create_or_update(datetime, "AAA", 10.0 + i as f64, 11.0, 1.0, 1.0);
+            cache.create_or_update(datetime, "BBB", 20.0 + i as f64, 21.0, 2.0, 2.0);
+        }
+
+        cache.create_or_update(date + Duration::minutes(3), "AAA", 13.0, 14.0, 1.0, 1.0);
+
+        let components = vec![("AAA".to_string(), 1.0), ("BBB".to_string(), 1.0)];
+        let index = cache.build_index(&components, CandleType::Minute, Side::Bid, date, date + Duration::minutes(4));
+        let candles = index.get_by_date_range(date, date + Duration::minutes(4));
+
+        assert_eq!(index.candle_type, CandleType::Minute);
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[0].open, 15.0);
+        assert_eq!(candles[2].close, 17.0);
+        assert_eq!(candles[2].volume, 3.0);
+    }
+
+    #[tokio::test]
+    async fn active_instruments() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "STALE", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(5), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(6), "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+
+        let from = date + Duration::minutes(1);
+        let to = date + Duration::minutes(10);
+
+        assert_eq!(cache.get_instruments().len(), 3);
+        assert_eq!(cache.active_instruments(CandleType::Minute, from, to), vec!["BTCUSD", "EURUSD"]);
+        assert!(cache.active_instruments(CandleType::Hour, from, to).is_empty());
+    }
+
+    #[tokio::test]
+    async fn remove_before_evicts_oldest_first() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let evicted_by_callback = evicted.clone();
+        cache.set_on_evict(Box::new(move |instrument, candle_type, datetime| {
+            evicted_by_callback
+                .lock()
+                .unwrap()
+                .push((instrument.to_string(), candle_type, datetime));
+        }));
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in (0..5).rev() {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+            cache.create_or_update(date + Duration::minutes(i), "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+        }
+
+        cache.remove_before(date + Duration::hours(1), None);
+        let evicted = evicted.lock().unwrap();
+        let mut expected = vec![
+            ("BTCUSD".to_string(), CandleType::Minute, date),
+            ("EURUSD".to_string(), CandleType::Minute, date),
+            ("BTCUSD".to_string(), CandleType::Hour, date),
+            ("EURUSD".to_string(), CandleType::Hour, date),
+        ];
+
+        for i in 1..5 {
+            expected.push(("BTCUSD".to_string(), CandleType::Minute, date + Duration::minutes(i)));
+            expected.push(("EURUSD".to_string(), CandleType::Minute, date + Duration::minutes(i)));
+        }
+
+        assert_eq!(*evicted, expected);
+    }
+
+    #[tokio::test]
+    async fn from_ticks() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let ticks: Vec<Tick> = (0..10)
+            .map(|i| {
+                let instrument = if i % 2 == 0 { "EURUSD" } else { "BTCUSD" };
+
+                (date + Duration::seconds(i * 20), instrument.to_string(), i as f64, i as f64 + 1.0, 1.0, 2.0)
+            })
+            .collect();
+
+        let cache = CandlesCache::from_ticks(vec![CandleType::Minute, CandleType::Hour], &ticks);
+        let candle = cache
+            .get(&BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(2)))
+            .unwrap();
+
+        assert_eq!(cache.len(), 3 + 4 + 2);
+        assert_eq!(candle.bid_data.open, 6.0);
+        assert_eq!(candle.bid_data.close, 8.0);
+        assert_eq!(candle.ask_data.volume, 4.0);
+        assert_eq!(cache.get(&BidAskCandle::generate_id("BTCUSD", &CandleType::Hour, date)).unwrap().bid_data.close, 9.0);
+    }
+
+    #[tokio::test]
+    async fn get_recent() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..120 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 1.0, 1.0, 1.0);
+        }
+
+        let now = date + Duration::minutes(119) + Duration::seconds(30);
+        let candles = cache.get_recent("EURUSD", &CandleType::Minute, Side::Ask, Duration::hours(1), now);
+
+        assert_eq!(candles.len(), 60);
+        assert_eq!(candles.first().unwrap().close, 61.0);
+        assert_eq!(candles.last().unwrap().close, 120.0);
+        assert!(cache.get_recent("BTCUSD", &CandleType::Minute, Side::Ask, Duration::hours(1), now).is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_after_skips_unknown_candle_types() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.insert(BidAskCandle {
+            candle_type: CandleType::Day,
+            datetime: date,
+            instrument: "EURUSD".into(),
+            bid_data: CandleData::new(date, 1.0, 1.0),
+            ask_data: CandleData::new(date, 1.2, 1.0),
+        });
+
+        let candles = cache.get_after(date).unwrap();
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(candles.len(), 1);
+        assert_eq!(candles[0].candle_type, CandleType::Minute);
+    }
+
+    #[tokio::test]
+    async fn get_interval_candles() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::FiveMinutes, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..60 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 1.0, 1.0, 1.0);
+        }
+
+        let ten_minutes = CandleInterval::from_minutes(10).unwrap();
+        let candles = cache
+            .get_interval_candles("EURUSD", &ten_minutes, Side::Bid, date + Duration::minutes(5), date + Duration::minutes(35))
+            .unwrap();
+
+        assert_eq!(
+            candles.iter().map(|candle| (candle.open, candle.close, candle.volume)).collect::<Vec<_>>(),
+            vec![(10.0, 19.0, 10.0), (20.0, 29.0, 10.0), (30.0, 39.0, 10.0)]
+        );
+
+        let seven_minutes = CandleInterval::from_minutes(7).unwrap();
+        let candles = cache.get_interval_candles("EURUSD", &seven_minutes, Side::Ask, date, date + Duration::minutes(14)).unwrap();
+
+        assert_eq!(candles.iter().map(|candle| candle.tick_count).collect::<Vec<_>>(), vec![7, 7]);
+        assert!(CandlesCache::new(vec![CandleType::Hour])
+            .get_interval_candles("EURUSD", &ten_minutes, Side::Bid, date, date + Duration::hours(1))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn get_multi_timeframe() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour, CandleType::Day]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..3 {
+            cache.create_or_update(date + Duration::minutes(i * 40), "EURUSD", 1.0 + i as f64, 2.0 + i as f64, 1.0, 1.0);
+        }
+
+        let candles = cache.get_multi_timeframe(
+            "EURUSD",
+            Side::Bid,
+            &[CandleType::Minute, CandleType::Hour, CandleType::Day],
+            date,
+            date + Duration::days(1),
+        );
+
+        assert_eq!(candles.len(), 3);
+        assert_eq!(candles[&CandleType::Minute].len(), 3);
+        assert_eq!(candles[&CandleType::Hour].len(), 2);
+        assert_eq!(candles[&CandleType::Hour][1].open, 3.0);
+        assert_eq!(candles[&CandleType::Day].len(), 1);
+        assert_eq!(candles[&CandleType::Day][0].close, 3.0);
+    }
+
+    #[tokio::test]
+    async fn derive_from() {
+        let mut maintained = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let mut derived = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        assert!(derived.derive_from(CandleType::Hour, CandleType::Minute));
+        assert!(!derived.derive_from(CandleType::Minute, CandleType::Hour));
+        assert!(!derived.derive_from(CandleType::Day, CandleType::Minute));
+
+        let mut calendar = CandlesCache::new(vec![CandleType::ThreeDays, CandleType::Week, CandleType::Month]);
+
+        assert!(!calendar.derive_from(CandleType::Month, CandleType::Week));
+        assert!(!calendar.derive_from(CandleType::Week, CandleType::ThreeDays));
+        assert!(calendar.derived_types.is_empty());
+
+        for i in 0..150 {
+            let price = 1.0 + (i % 7) as f64 * 0.125;
+
+            for cache in [&mut maintained, &mut derived] {
+                cache.create_or_update(date + Duration::seconds(i * 50), "EURUSD", price, price + 0.5, 1.0, 2.0);
+            }
+        }
+
+        let to = date + Duration::hours(3);
+        let expected = maintained.get_by_date_range_labeled("EURUSD", &CandleType::Hour, Side::Ask, date, to);
+        let actual = derived.get_by_date_range_labeled("EURUSD", &CandleType::Hour, Side::Ask, date, to);
+
+        assert_eq!(derived.len(), maintained.len() - expected.len());
+        assert_eq!(actual.len(), 3);
+        assert_eq!(
+            actual.iter().map(|candle| candle.data.clone()).collect::<Vec<_>>(),
+            expected.iter().map(|candle| candle.data.clone()).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn last_price() {
+        let mut cache = CandlesCache::new(vec![CandleType::Hour, CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.5, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(2), "EURUSD", 1.25, 1.75, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(1), "EURUSD", 1.125, 1.625, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(3), "BTCUSD", 100.0, 101.0, 1.0, 1.0);
+
+        assert_eq!(cache.last_price("EURUSD", Side::Bid), Some(1.25));
+        assert_eq!(cache.last_price("EURUSD", Side::Ask), Some(1.75));
+        assert_eq!(cache.last_price("XAUUSD", Side::Bid), None);
+    }
+
+    #[tokio::test]
+    async fn create_or_update_sums_volume() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..4 {
+            cache.create_or_update(date + Duration::seconds(i * 10), "EURUSD", 1.0, 1.2, 1.5, 2.5);
+        }
+
+        let candle = cache.get(&BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date)).unwrap();
+        let json = serde_json::to_value(&candle.bid_data).unwrap();
+
+        assert_eq!(candle.bid_data.volume, 6.0);
+        assert_eq!(candle.ask_data.volume, 10.0);
+        assert_eq!(json["volume"], 6.0);
+    }
+
+    #[tokio::test]
+    async fn get_after_and_remove_before_boundary() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date - Duration::minutes(1), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+
+        let boundary_id = BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date);
+        let is_after = cache.get_after(date).unwrap().iter().any(|candle| candle.get_id() == boundary_id);
+        let removed_count = cache.remove_before(date, None);
+
+        assert!(is_after);
+        assert_eq!(removed_count, 1);
+        assert!(cache.contains(&boundary_id));
+    }
+
+    #[tokio::test]
+    async fn get_mid_by_date_range() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.5, 1.0, 2.0);
+        cache.create_or_update(date + Duration::seconds(10), "EURUSD", 2.0, 2.5, 1.0, 2.0);
+        cache.create_or_update(date + Duration::seconds(20), "EURUSD", 0.5, 1.0, 1.0, 2.0);
+        cache.create_or_update(date + Duration::minutes(2), "EURUSD", 3.0, 4.0, 1.0, 2.0);
+
+        let candles = cache.get_mid_by_date_range("EURUSD", &CandleType::Minute, date, date + Duration::minutes(3));
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            (candles[0].open, candles[0].high, candles[0].low, candles[0].close, candles[0].volume),
+            (1.25, 2.25, 0.75, 0.75, 9.0)
+        );
+        assert_eq!(candles[1].close, 3.5);
+    }
+
+    #[tokio::test]
+    async fn get_by_date_range_inclusive() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..3 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 1.0, 1.0, 1.0);
+        }
+
+        let date_to = date + Duration::minutes(1);
+        let candles = cache.get_by_date_range_inclusive("EURUSD", &CandleType::Minute, Side::Bid, date, date_to);
+
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].close, 1.0);
+        assert_eq!(
+            cache.get_by_date_range_labeled("EURUSD", &CandleType::Minute, Side::Bid, date, date_to).len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn common_range() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..10 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+            cache.create_or_update(date + Duration::minutes(i + 6), "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+            cache.create_or_update(date + Duration::minutes(i + 20), "XAUUSD", 1000.0, 1001.0, 1.0, 1.0);
+        }
+
+        assert_eq!(
+            cache.common_range("EURUSD", "BTCUSD", &CandleType::Minute),
+            Some((date + Duration::minutes(6), date + Duration::minutes(9)))
+        );
+        assert_eq!(cache.common_range("EURUSD", "XAUUSD", &CandleType::Minute), None);
+        assert_eq!(cache.common_range("EURUSD", "USDJPY", &CandleType::Minute), None);
+    }
+
+    #[tokio::test]
+    async fn set_precision() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.set_precision("EURUSD", 5);
+        cache.set_precision("USDJPY", 3);
+        cache.create_or_update(date, "EURUSD", 1.123456, 1.123474, 1.0, 1.0);
+        cache.create_or_update(date, "USDJPY", 150.123456, 150.123474, 1.0, 1.0);
+        cache.create_or_update(date, "BTCUSD", 100.123456, 100.123474, 1.0, 1.0);
+
+        let get = |instrument| cache.get(&BidAskCandle::generate_id(instrument, &CandleType::Minute, date)).unwrap();
+
+        assert_eq!((get("EURUSD").bid_data.close, get("EURUSD").ask_data.close), (1.12346, 1.12347));
+        assert_eq!((get("USDJPY").bid_data.close, get("USDJPY").ask_data.close), (150.123, 150.123));
+        assert_eq!(get("BTCUSD").bid_data.close, 100.123456);
+    }
+
+    #[tokio::test]
+    async fn project() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..120 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 1.0, 1.0, 2.0);
+            cache.create_or_update(date + Duration::minutes(i), "BTCUSD", 1000.0 - i as f64, 1001.0 - i as f64, 3.0, 4.0);
+        }
+
+        let projected = cache.project(&CandleType::Minute, CandleType::Hour).unwrap();
+        let get = |instrument, hours| {
+            projected
+                .get(&BidAskCandle::generate_id(instrument, &CandleType::Hour, date + Duration::hours(hours)))
+                .unwrap()
+        };
+
+        assert_eq!(projected.len(), 4);
+        assert_eq!(projected.get_candle_types(), &[CandleType::Hour]);
+
+        let eurusd = get("EURUSD", 1);
+        assert_eq!(
+            (eurusd.bid_data.open, eurusd.bid_data.high, eurusd.bid_data.low, eurusd.bid_data.close),
+            (60.0, 119.0, 60.0, 119.0)
+        );
+        assert_eq!(eurusd.ask_data.volume, 120.0);
+
+        let btcusd = get("BTCUSD", 0);
+        assert_eq!(
+            (btcusd.ask_data.open, btcusd.ask_data.high, btcusd.ask_data.low, btcusd.ask_data.close),
+            (1001.0, 1001.0, 942.0, 942.0)
+        );
+        assert_eq!(btcusd.bid_data.volume, 180.0);
+        assert!(cache.project(&CandleType::Minute, CandleType::Minute).is_none());
+    }
+
+    #[tokio::test]
+    async fn project_rejects_non_nesting_types() {
+        let mut cache = CandlesCache::new(vec![CandleType::ThreeDays, CandleType::Week]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.5, 1.0, 1.0);
+
+        assert!(cache.project(&CandleType::Week, CandleType::Month).is_none());
+        assert!(cache.project(&CandleType::ThreeDays, CandleType::Week).is_none());
+        assert!(cache.project(&CandleType::Week, CandleType::Week).is_none());
+    }
+
+    #[tokio::test]
+    async fn get_latest() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in [3, 0, 70, 5] {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64, 1.0, 1.0);
+            cache.create_or_update(date + Duration::minutes(i / 2), "BTCUSD", i as f64, i as f64, 1.0, 1.0);
+        }
+
+        cache.ingest_ohlc(
+            "XAUUSD",
+            CandleType::Hour,
+            date,
+            CandleData::new(date, 1.0, 1.0),
+            CandleData::new(date, 1.0, 1.0),
+        );
+
+        let latest = |instrument, candle_type| {
+            cache.get_latest(instrument, &candle_type).map(|candle| (candle.datetime, candle.bid_data.close))
+        };
+
+        assert_eq!(latest("EURUSD", CandleType::Minute), Some((date + Duration::minutes(70), 70.0)));
+        assert_eq!(latest("EURUSD", CandleType::Hour), Some((date + Duration::hours(1), 70.0)));
+        assert_eq!(latest("BTCUSD", CandleType::Minute), Some((date + Duration::minutes(35), 70.0)));
+        assert_eq!(latest("BTCUSD", CandleType::Hour), Some((date, 70.0)));
+        assert_eq!(latest("XAUUSD", CandleType::Hour), Some((date, 1.0)));
+        assert_eq!(latest("XAUUSD", CandleType::Minute), None);
+
+        cache.remove_before(date + Duration::hours(2), Some(CandleType::Minute));
+
+        assert!(cache.get_latest("EURUSD", &CandleType::Minute).is_none());
+        assert!(cache.get_latest("EURUSD", &CandleType::Hour).is_some());
+    }
+
+    #[tokio::test]
+    async fn remove_keeping_last() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..5 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        }
+
+        cache.create_or_update(date, "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(1), "BTCUSD", 100.0, 102.0, 1.0, 1.0);
+
+        let removed_count = cache.remove_keeping_last(2);
+        let ids = [
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(2)),
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(3)),
+            BidAskCandle::generate_id("EURUSD", &CandleType::Minute, date + Duration::minutes(4)),
+            BidAskCandle::generate_id("EURUSD", &CandleType::Hour, date),
+            BidAskCandle::generate_id("BTCUSD", &CandleType::Minute, date),
+        ];
+
+        assert_eq!(removed_count, 3);
+        assert_eq!(cache.len(), 6);
+        assert_eq!(cache.contains_many(&ids), vec![false, true, true, true, true]);
+        assert_eq!(cache.remove_keeping_last(2), 0);
+        assert_eq!(cache.remove_keeping_last(0), 6);
+        assert!(cache.get_latest("EURUSD", &CandleType::Minute).is_none());
+    }
+
+    #[tokio::test]
+    async fn correlation() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..10 {
+            let price = 1.0 + (i * i % 7) as f64;
+            let datetime = date + Duration::minutes(i);
+
+            cache.create_or_update(datetime, "A", price, price, 1.0, 1.0);
+            cache.create_or_update(datetime, "CORRELATED", 2.0 * price + 5.0, 0.0, 1.0, 1.0);
+            cache.create_or_update(datetime, "ANTI", 10.0 - price, 0.0, 1.0, 1.0);
+            cache.create_or_update(datetime, "FLAT", 3.0, 3.0, 1.0, 1.0);
+        }
+
+        cache.create_or_update(date + Duration::minutes(30), "LATE", 1.0, 1.0, 1.0, 1.0);
+
+        let to = date + Duration::hours(1);
+        let correlation = |b| cache.correlation("A", b, &CandleType::Minute, Side::Bid, date, to);
+
+        assert!((correlation("CORRELATED").unwrap() - 1.0).abs() < 1e-9);
+        assert!((correlation("ANTI").unwrap() + 1.0).abs() < 1e-9);
+        assert_eq!(correlation("FLAT"), None);
+        assert_eq!(correlation("LATE"), None);
+    }
+
+    #[tokio::test]
+    async fn compact_old() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..180 {
+            let datetime = date + Duration::minutes(i);
+            let price = 1.0 + (i % 60) as f64;
+
+            for instrument in ["EURUSD", "BTCUSD"] {
+                cache.insert(BidAskCandle {
+                    candle_type: CandleType::Minute,
+                    datetime,
+                    instrument: instrument.into(),
+                    bid_data: CandleData::new(datetime, price, 1.0),
+                    ask_data: CandleData::new(datetime, price + 0.5, 2.0),
+                });
+            }
+        }
+
+        let existing_hour = date + Duration::hours(1);
+        cache.insert(BidAskCandle {
+            candle_type: CandleType::Hour,
+            datetime: existing_hour,
+            instrument: "EURUSD".into(),
+            bid_data: CandleData::new(existing_hour, 100.0, 1.0),
+            ask_data: CandleData::new(existing_hour, 100.0, 1.0),
+        });
+
+        let removed = cache.compact_old("EURUSD", date + Duration::minutes(150));
+
+        assert_eq!(removed, 120);
+        assert_eq!(cache.len(), 60 + 2 + 180);
+
+        let hour = cache
+            .get(&BidAskCandle::generate_id("EURUSD", &CandleType::Hour, date))
+            .unwrap();
+        assert_eq!(hour.bid_data.open, 1.0);
+        assert_eq!(hour.bid_data.close, 60.0);
+        assert_eq!(hour.bid_data.high, 60.0);
+        assert_eq!(hour.bid_data.low, 1.0);
+        assert_eq!(hour.bid_data.volume, 60.0);
+        assert_eq!(hour.ask_data.close, 60.5);
+        assert_eq!(hour.ask_data.volume, 120.0);
+
+        let kept_hour = cache
+            .get(&BidAskCandle::generate_id("EURUSD", &CandleType::Hour, existing_hour))
+            .unwrap();
+        assert_eq!(kept_hour.bid_data.close, 100.0);
+
+        let minutes = cache.get_by_date_range_inclusive(
+            "EURUSD",
+            &CandleType::Minute,
+            Side::Bid,
+            date,
+            date + Duration::hours(3),
+        );
+        assert_eq!(minutes.len(), 60);
+        assert_eq!(minutes[0].datetime, date + Duration::hours(2));
+        assert_eq!(cache.compact_old("EURUSD", date + Duration::minutes(150)), 0);
+
+        let evicted = Arc::new(Mutex::new(0));
+        let evicted_count = evicted.clone();
+        cache.set_on_evict(Box::new(move |_instrument, _candle_type, _datetime| *evicted_count.lock().unwrap() += 1));
+
+        assert_eq!(cache.compact_old("BTCUSD", date + Duration::minutes(150)), 120);
+        assert_eq!(*evicted.lock().unwrap(), 0);
+
+        let mut minutes_only = CandlesCache::new(vec![CandleType::Minute]);
+        minutes_only.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        assert_eq!(minutes_only.compact_old("EURUSD", date + Duration::hours(2)), 0);
+    }
+
+    #[tokio::test]
+    async fn duplicated_candle_types_with_get_after() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for candle_types in [
+            vec![CandleType::Day, CandleType::Day, CandleType::Minute],
+            vec![CandleType::Day, CandleType::Minute, CandleType::Day],
+        ] {
+            let mut cache = CandlesCache::new(candle_types);
+
+            cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+
+            assert_eq!(cache.get_candle_types(), &[CandleType::Minute, CandleType::Day]);
+            assert_eq!(cache.len(), 2);
+            assert_eq!(cache.get_after(date).unwrap().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn create_or_update_many() {
+        let candle_types = vec![CandleType::Minute, CandleType::FiveMinutes, CandleType::Hour, CandleType::Day];
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 23, 0, 0).unwrap();
+        let quotes: Vec<Quote> = (0..5_000)
+            .map(|i| {
+                let price = 1.0 + (i * 7 % 13) as f64 / 1000.0;
+
+                (date + Duration::seconds(i * 7), price, price + 0.0002, 1.0, (i % 3) as f64)
+            })
+            .collect();
+        let mut one_by_one = CandlesCache::new(candle_types.clone());
+        let mut batched = CandlesCache::new(candle_types);
+
+        one_by_one.create_or_update(date, "EURUSD", 1.5, 1.6, 1.0, 1.0);
+        batched.create_or_update(date, "EURUSD", 1.5, 1.6, 1.0, 1.0);
+        one_by_one.set_precision("EURUSD", 3);
+        batched.set_precision("EURUSD", 3);
+
+        for (datetime, bid, ask, bid_vol, ask_vol) in quotes.iter().copied() {
+            one_by_one.create_or_update(datetime, "EURUSD", bid, ask, bid_vol, ask_vol);
+        }
+
+        assert!(batched.create_or_update_many("EURUSD", &quotes));
+        assert_eq!(batched.len(), one_by_one.len());
+        assert_eq!(batched.get_all(), one_by_one.get_all());
+        assert_eq!(batched.latest_dates, one_by_one.latest_dates);
+    }
+
+    #[tokio::test]
+    async fn estimated_bytes() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let build = |minutes: i64| {
+            let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+
+            for i in 0..minutes {
+                cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+                cache.create_or_update(date + Duration::minutes(i), "BTCUSD", 1.0, 1.2, 1.0, 1.0);
+            }
+
+            cache
+        };
+
+        assert_eq!(CandlesCache::new(vec![CandleType::Minute]).estimated_bytes(), 0);
+
+        let small = build(1_000);
+        let large = build(4_000);
+        let ratio = large.estimated_bytes() as f64 / small.estimated_bytes() as f64;
+        let candle_ratio = large.len() as f64 / small.len() as f64;
+
+        assert!(small.estimated_bytes() >= small.len() * std::mem::size_of::<BidAskCandle>());
+        assert!(ratio > candle_ratio * 0.5 && ratio < candle_ratio * 2.0, "{}", ratio);
+    }
+
+    #[tokio::test]
+    async fn extract_before_all() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute, CandleType::Hour]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        for i in 0..90 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", i as f64, i as f64 + 0.5, 1.0, 1.0);
+        }
+
+        cache.create_or_update(date + Duration::minutes(5), "BTCUSD", 100.0, 101.0, 1.0, 1.0);
+
+        let cutoff = date + Duration::minutes(30);
+        let extracted = cache.extract_before_all(cutoff);
+        let bids = &extracted[&("EURUSD".to_string(), CandleType::Minute, Side::Bid)];
+        let asks = &extracted[&("EURUSD".to_string(), CandleType::Minute, Side::Ask)];
+
+        assert_eq!(extracted.len(), 2 * 4);
+        assert_eq!(bids.len(), 30);
+        assert_eq!(bids.iter().map(|candle| candle.close).collect::<Vec<_>>(), (0..30).map(|i| i as f64).collect::<Vec<_>>());
+        assert_eq!(asks[29].close, 29.5);
+        assert_eq!(extracted[&("EURUSD".to_string(), CandleType::Hour, Side::Bid)].len(), 1);
+        assert_eq!(extracted[&("BTCUSD".to_string(), CandleType::Hour, Side::Ask)][0].close, 101.0);
+        assert_eq!(cache.len(), 60 + 1);
+        assert!(cache.get_latest("BTCUSD", &CandleType::Minute).is_none());
+        assert_eq!(cache.get_latest("EURUSD", &CandleType::Minute).unwrap().datetime, date + Duration::minutes(89));
+        assert!(cache.extract_before_all(cutoff).is_empty());
+    }
+
+    #[tokio::test]
+    async fn candle_counts() {
+        let mut cache = CandlesCache::new(vec![
+            CandleType::Minute,
+            CandleType::Hour,
+            CandleType::Day,
+            CandleType::Month,
+            CandleType::Week,
+        ]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.derive_from(CandleType::Week, CandleType::Day);
+
+        for i in 0..1440 {
+            cache.create_or_update(date + Duration::minutes(i), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        }
+
+        cache.create_or_update(date, "BTCUSD", 100.0, 101.0, 1.0, 1.0);
+
+        let counts = cache.candle_counts("EURUSD");
+
+        assert_eq!(counts.len(), 4);
+        assert_eq!(counts[&CandleType::Minute], 1440);
+        assert_eq!(counts[&CandleType::Hour], 24);
+        assert_eq!(counts[&CandleType::Day], 1);
+        assert_eq!(counts[&CandleType::Month], 1);
+        assert_eq!(cache.candle_counts("BTCUSD")[&CandleType::Minute], 1);
+        assert_eq!(cache.candle_counts("UNKNOWN")[&CandleType::Hour], 0);
+    }
+
+    #[tokio::test]
+    async fn insert_if_absent() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candle = |price| BidAskCandle {
+            candle_type: CandleType::Minute,
+            datetime: date,
+            instrument: "EURUSD".into(),
+            bid_data: CandleData::new(date, price, 1.0),
+            ask_data: CandleData::new(date, price + 0.5, 1.0),
+        };
+
+        assert!(cache.insert_if_absent(candle(1.0)));
+        assert!(!cache.insert_if_absent(candle(2.0)));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get_latest("EURUSD", &CandleType::Minute).unwrap().bid_data.close, 1.0);
+        assert!(cache.get_instruments().contains("EURUSD"));
+    }
+
+    #[tokio::test]
+    async fn insert_respects_max_instruments() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        cache.max_instruments = Some(1);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candle = |instrument: &str, datetime| BidAskCandle {
+            candle_type: CandleType::Minute,
+            datetime,
+            instrument: instrument.into(),
+            bid_data: CandleData::new(datetime, 1.0, 1.0),
+            ask_data: CandleData::new(datetime, 1.2, 1.0),
+        };
+
+        assert!(cache.insert(candle("EURUSD", date)));
+        assert!(cache.insert(candle("EURUSD", date + Duration::minutes(1))));
+        assert!(!cache.insert(candle("BTCUSD", date)));
+        assert!(!cache.insert_if_absent(candle("BTCUSD", date)));
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get_instruments().len(), 1);
+        assert!(!cache.contains(&BidAskCandle::generate_id("BTCUSD", &CandleType::Minute, date)));
+    }
+
+    #[tokio::test]
+    async fn eviction_frees_max_instruments_slot() {
+        let mut cache = CandlesCache::new(vec![CandleType::Minute]);
+        cache.max_instruments = Some(2);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        cache.create_or_update(date, "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date + Duration::minutes(5), "EURUSD", 1.0, 1.2, 1.0, 1.0);
+        cache.create_or_update(date, "BTCUSD", 1.0, 1.2, 1.0, 1.0);
+
+        assert!(!cache.create_or_update(date, "ETHUSD", 1.0, 1.2, 1.0, 1.0));
+
+        cache.remove_before(date + Duration::minutes(1), None);
+
+        assert_eq!(cache.get_instruments().len(), 1);
+        assert!(cache.get_instruments().contains("EURUSD"));
+        assert!(cache.create_or_update(date + Duration::minutes(5), "ETHUSD", 1.0, 1.2, 1.0, 1.0));
+
+        cache.extract_before_all(date + Duration::minutes(10));
+
+        assert!(cache.get_instruments().is_empty());
     }
 }
@@ -1,2 +1,3 @@
 pub mod candle_prices_cache;
+pub mod candle_prices_view;
 pub mod candles_cache;
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod models;
-pub mod caches;
\ No newline at end of file
+pub mod caches;
+pub mod utils;
\ No newline at end of file
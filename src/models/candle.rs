@@ -1,8 +1,109 @@
-use chrono::{DateTime, Utc};
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
 use compact_str::CompactString;
-use super::{candle_type::CandleType, candle_data::CandleData};
+use super::{candle_type::CandleType, candle_interval::CandleInterval, candle_data::CandleData, side::Side};
+use crate::utils::crc32::crc32;
+
+const ID_DELIMITER: char = ':';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CandleBytesError {
+    UnexpectedEnd,
+    TrailingBytes(usize),
+    /// Stored checksum doesn't match the payload
+    CorruptData { expected: u32, actual: u32 },
+    UnknownCandleType(i32),
+    InvalidDatetime(i64, u32),
+    InvalidInstrument,
+}
+
+impl fmt::Display for CandleBytesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CandleBytesError::UnexpectedEnd => write!(f, "Candle bytes end unexpectedly"),
+            CandleBytesError::TrailingBytes(count) => write!(f, "Candle bytes have {} trailing bytes", count),
+            CandleBytesError::CorruptData { expected, actual } => {
+                write!(f, "Candle bytes checksum {:08x} doesn't match payload checksum {:08x}", expected, actual)
+            }
+            CandleBytesError::UnknownCandleType(candle_type) => write!(f, "Unknown candle type {}", candle_type),
+            CandleBytesError::InvalidDatetime(seconds, nanos) => {
+                write!(f, "Date {}s {}ns is out of range", seconds, nanos)
+            }
+            CandleBytesError::InvalidInstrument => write!(f, "Instrument is not valid utf-8"),
+        }
+    }
+}
+
+impl std::error::Error for CandleBytesError {}
+
+/// Reads little-endian fields of `BidAskCandle::to_bytes` layout
+struct BytesReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> BytesReader<'a> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], CandleBytesError> {
+        let bytes = self.take_slice(N)?;
+
+        Ok(bytes.try_into().expect("slice has N bytes"))
+    }
+
+    fn take_slice(&mut self, len: usize) -> Result<&'a [u8], CandleBytesError> {
+        if self.bytes.len() < len {
+            return Err(CandleBytesError::UnexpectedEnd);
+        }
+
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+
+        Ok(head)
+    }
+
+    fn read_f64(&mut self) -> Result<f64, CandleBytesError> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+
+    fn read_datetime(&mut self) -> Result<DateTime<Utc>, CandleBytesError> {
+        let seconds = i64::from_le_bytes(self.take()?);
+        let nanos = u32::from_le_bytes(self.take()?);
+
+        Utc.timestamp_opt(seconds, nanos)
+            .single()
+            .ok_or(CandleBytesError::InvalidDatetime(seconds, nanos))
+    }
 
-#[derive(Clone)]
+    fn read_data(&mut self) -> Result<CandleData, CandleBytesError> {
+        Ok(CandleData {
+            open: self.read_f64()?,
+            close: self.read_f64()?,
+            high: self.read_f64()?,
+            low: self.read_f64()?,
+            volume: self.read_f64()?,
+            tick_count: u64::from_le_bytes(self.take()?),
+            datetime: self.read_datetime()?,
+            open_datetime: self.read_datetime()?,
+        })
+    }
+}
+
+fn write_datetime(bytes: &mut Vec<u8>, datetime: DateTime<Utc>) {
+    bytes.extend_from_slice(&datetime.timestamp().to_le_bytes());
+    bytes.extend_from_slice(&datetime.timestamp_subsec_nanos().to_le_bytes());
+}
+
+fn write_data(bytes: &mut Vec<u8>, data: &CandleData) {
+    for value in [data.open, data.close, data.high, data.low, data.volume] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    bytes.extend_from_slice(&data.tick_count.to_le_bytes());
+
+    write_datetime(bytes, data.datetime);
+    write_datetime(bytes, data.open_datetime);
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct BidAskCandle {
     pub candle_type: CandleType,
     pub datetime: DateTime<Utc>,
@@ -17,20 +118,276 @@ impl BidAskCandle {
         self.ask_data.update(datetime, ask, ask_vol);
     }
 
+    pub fn get_data(&self, side: Side) -> &CandleData {
+        match side {
+            Side::Bid => &self.bid_data,
+            Side::Ask => &self.ask_data,
+        }
+    }
+
+    pub fn get_data_mut(&mut self, side: Side) -> &mut CandleData {
+        match side {
+            Side::Bid => &mut self.bid_data,
+            Side::Ask => &mut self.ask_data,
+        }
+    }
+
+    /// Generates id in `{candle_type}:{instrument}:{start timestamp}` format.
+    /// Instrument may contain the delimiter since parsing splits by the first and last one
     pub fn generate_id(
         instrument: &str,
         candle_type: &CandleType,
         datetime: DateTime<Utc>,
     ) -> String {
         format!(
-            "{}{}{}",
+            "{}{}{}{}{}",
             candle_type.to_owned() as u8,
-            instrument.to_string(),
+            ID_DELIMITER,
+            instrument,
+            ID_DELIMITER,
             candle_type.get_start_date(datetime).timestamp(),
         )
     }
 
+    /// Generates id of an interval candle in `{interval}:{instrument}:{start timestamp}` format, e.g. `10m:BTC:946684800`.
+    /// Interval prefix always has a unit letter, so it never equals an id of a `CandleType` candle
+    pub fn generate_interval_id(instrument: &str, interval: &CandleInterval, datetime: DateTime<Utc>) -> String {
+        format!(
+            "{}{}{}{}{}",
+            interval,
+            ID_DELIMITER,
+            instrument,
+            ID_DELIMITER,
+            interval.get_start_date(datetime).timestamp(),
+        )
+    }
+
+    /// Parses id generated by `generate_id` into (candle_type, instrument, start date)
+    pub fn parse_id(id: &str) -> Option<(CandleType, String, DateTime<Utc>)> {
+        let (candle_type, rest) = id.split_once(ID_DELIMITER)?;
+        let (instrument, timestamp) = rest.rsplit_once(ID_DELIMITER)?;
+        let candle_type = CandleType::try_from(candle_type.parse::<i32>().ok()?).ok()?;
+        let datetime = Utc.timestamp_opt(timestamp.parse().ok()?, 0).single()?;
+
+        Some((candle_type, instrument.to_string(), datetime))
+    }
+
     pub fn get_id(&self) -> String {
         BidAskCandle::generate_id(&self.instrument, &self.candle_type, self.datetime)
     }
-}
\ No newline at end of file
+
+    /// Encodes candle in little-endian layout: CRC-32 u32 of the rest, candle type i32, date (seconds i64, nanos u32),
+    /// instrument (length u32, utf-8), then bid and ask data as open, close, high, low, volume f64,
+    /// tick_count u64, datetime and open_datetime
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 * 72 + 24 + self.instrument.len());
+
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(self.candle_type.to_owned() as i32).to_le_bytes());
+        write_datetime(&mut bytes, self.datetime);
+        bytes.extend_from_slice(&(self.instrument.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.instrument.as_bytes());
+        write_data(&mut bytes, &self.bid_data);
+        write_data(&mut bytes, &self.ask_data);
+
+        let checksum = crc32(&bytes[4..]);
+        bytes[..4].copy_from_slice(&checksum.to_le_bytes());
+
+        bytes
+    }
+
+    /// Decodes candle encoded by `to_bytes`, verifying its checksum first
+    pub fn from_bytes(bytes: &[u8]) -> Result<BidAskCandle, CandleBytesError> {
+        let mut reader = BytesReader { bytes };
+        let expected = u32::from_le_bytes(reader.take()?);
+        let actual = crc32(reader.bytes);
+
+        if expected != actual {
+            return Err(CandleBytesError::CorruptData { expected, actual });
+        }
+
+        let candle_type = i32::from_le_bytes(reader.take()?);
+        let candle_type =
+            CandleType::try_from(candle_type).map_err(|_| CandleBytesError::UnknownCandleType(candle_type))?;
+        let datetime = reader.read_datetime()?;
+        let instrument_len = u32::from_le_bytes(reader.take()?) as usize;
+        let instrument = std::str::from_utf8(reader.take_slice(instrument_len)?)
+            .map_err(|_| CandleBytesError::InvalidInstrument)?;
+        let candle = BidAskCandle {
+            candle_type,
+            datetime,
+            instrument: instrument.into(),
+            bid_data: reader.read_data()?,
+            ask_data: reader.read_data()?,
+        };
+
+        if !reader.bytes.is_empty() {
+            return Err(CandleBytesError::TrailingBytes(reader.bytes.len()));
+        }
+
+        Ok(candle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::candle::{BidAskCandle, CandleBytesError};
+    use crate::models::candle_data::CandleData;
+    use crate::models::candle_interval::CandleInterval;
+    use crate::models::candle_type::CandleType;
+    use crate::models::side::Side;
+    use crate::utils::crc32::crc32;
+    use chrono::{DateTime, TimeZone, Utc};
+    use proptest::prelude::*;
+
+    const CANDLE_TYPES: [CandleType; 17] = [
+        CandleType::Minute,
+        CandleType::Hour,
+        CandleType::Day,
+        CandleType::Month,
+        CandleType::ThreeMinutes,
+        CandleType::FiveMinutes,
+        CandleType::FifteenMinutes,
+        CandleType::ThirtyMinutes,
+        CandleType::TwoHours,
+        CandleType::FourHours,
+        CandleType::SixHours,
+        CandleType::EightHours,
+        CandleType::TwelveHours,
+        CandleType::ThreeDays,
+        CandleType::SevenDays,
+        CandleType::Session,
+        CandleType::Week,
+    ];
+
+    proptest! {
+        #[test]
+        fn parse_generated_id(
+            instrument in "[0-9]{0,3}\\PC{0,12}",
+            candle_type in proptest::sample::select(CANDLE_TYPES.to_vec()),
+            timestamp in 0i64..4_000_000_000,
+        ) {
+            let datetime = Utc.timestamp_opt(timestamp, 0).unwrap();
+            let start_date = candle_type.get_start_date(datetime);
+
+            let id = BidAskCandle::generate_id(&instrument, &candle_type, datetime);
+
+            prop_assert_eq!(BidAskCandle::parse_id(&id), Some((candle_type, instrument, start_date)));
+        }
+
+        #[test]
+        fn bytes_round_trip(
+            instrument in "\\PC{0,12}",
+            candle_type in proptest::sample::select(CANDLE_TYPES.to_vec()),
+            timestamp in -4_000_000_000i64..4_000_000_000,
+            nanos in 0u32..1_000_000_000,
+            prices in proptest::array::uniform5(-1e9f64..1e9),
+        ) {
+            let datetime = Utc.timestamp_opt(timestamp, nanos).unwrap();
+            let mut bid_data = CandleData::new(datetime, prices[0], prices[4]);
+            bid_data.update(datetime + chrono::Duration::nanoseconds(1), prices[1], prices[3]);
+            let candle = BidAskCandle {
+                datetime: candle_type.get_start_date(datetime),
+                candle_type,
+                instrument: instrument.into(),
+                bid_data,
+                ask_data: CandleData::new(datetime, prices[2], -0.0),
+            };
+
+            prop_assert_eq!(BidAskCandle::from_bytes(&candle.to_bytes()), Ok(candle));
+        }
+    }
+
+    #[tokio::test]
+    async fn bytes_errors() {
+        let datetime: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candle = BidAskCandle {
+            candle_type: CandleType::Week,
+            datetime,
+            instrument: "EURUSD".into(),
+            bid_data: CandleData::new(datetime, 1.1, 1.0),
+            ask_data: CandleData::new(datetime, 1.2, 2.0),
+        };
+        let bytes = candle.to_bytes();
+
+        let with_checksum = |mut payload: Vec<u8>| {
+            let checksum = crc32(&payload[4..]);
+            payload[..4].copy_from_slice(&checksum.to_le_bytes());
+
+            payload
+        };
+
+        assert_eq!(BidAskCandle::from_bytes(&bytes[..3]), Err(CandleBytesError::UnexpectedEnd));
+        assert_eq!(
+            BidAskCandle::from_bytes(&with_checksum(bytes[..bytes.len() - 1].to_vec())),
+            Err(CandleBytesError::UnexpectedEnd)
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(BidAskCandle::from_bytes(&with_checksum(trailing)), Err(CandleBytesError::TrailingBytes(1)));
+
+        let mut unknown_type = bytes.clone();
+        unknown_type[4..8].copy_from_slice(&99i32.to_le_bytes());
+        assert_eq!(
+            BidAskCandle::from_bytes(&with_checksum(unknown_type)),
+            Err(CandleBytesError::UnknownCandleType(99))
+        );
+
+        for index in [0, 5, bytes.len() / 2, bytes.len() - 1] {
+            let mut corrupted = bytes.clone();
+            corrupted[index] ^= 0x10;
+
+            assert!(matches!(
+                BidAskCandle::from_bytes(&corrupted),
+                Err(CandleBytesError::CorruptData { .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn generate_id_is_unambiguous() {
+        let datetime: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        let id = BidAskCandle::generate_id("BTC1", &CandleType::Minute, datetime);
+        let other_id = BidAskCandle::generate_id("BTC", &CandleType::Minute, Utc.timestamp_opt(1946684800, 0).unwrap());
+
+        assert_eq!(id, "0:BTC1:946684800");
+        assert_ne!(id, other_id);
+        assert_eq!(BidAskCandle::parse_id("garbage"), None);
+        assert_eq!(BidAskCandle::parse_id("99:BTC:946684800"), None);
+    }
+
+    #[tokio::test]
+    async fn generate_interval_id() {
+        let datetime: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 7, 0).unwrap();
+        let five_minutes = CandleInterval::from_minutes(5).unwrap();
+        let id = BidAskCandle::generate_interval_id("BTC", &five_minutes, datetime);
+
+        assert_eq!(id, "5m:BTC:946685100");
+        assert_ne!(id, BidAskCandle::generate_interval_id("BTC", &CandleInterval::from_seconds(5).unwrap(), datetime));
+        assert_ne!(id, BidAskCandle::generate_id("BTC", &CandleType::FiveMinutes, datetime));
+        assert_eq!(BidAskCandle::parse_id(&id), None);
+    }
+
+    #[tokio::test]
+    async fn get_data_by_side() {
+        let datetime: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = BidAskCandle {
+            candle_type: CandleType::Minute,
+            datetime,
+            instrument: "EURUSD".into(),
+            bid_data: CandleData::new(datetime, 1.1, 1.0),
+            ask_data: CandleData::new(datetime, 1.2, 2.0),
+        };
+
+        assert_eq!(candle.get_data(Side::Bid).close, 1.1);
+        assert_eq!(candle.get_data(Side::Ask).close, 1.2);
+
+        candle.get_data_mut(Side::Ask).update(datetime, 1.3, 1.0);
+
+        assert_eq!(candle.get_data(Side::Ask).close, 1.3);
+        assert_eq!(candle.get_data(Side::Ask).volume, 3.0);
+        assert_eq!(candle.get_data(Side::Bid).close, 1.1);
+    }
+}
@@ -1,11 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde_derive::{Serialize, Deserialize};
 use serde_with::{serde_as, TimestampSecondsWithFrac};
 
-use super::candle_type::CandleType;
+use super::{candle_data_formats::LightweightChartCandle, candle_type::CandleType};
 
 #[serde_as]
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CandleData {
     pub open: f64,
     pub close: f64,
@@ -14,6 +14,13 @@ pub struct CandleData {
     #[serde_as(as = "TimestampSecondsWithFrac<f64>")]
     pub datetime: DateTime<Utc>,
     pub volume: f64,
+    /// Count of ticks aggregated into the candle. Candles serialized without it are counted as one tick
+    #[serde(default = "default_tick_count")]
+    pub tick_count: u64,
+    /// Date of the tick that set `open`: the earliest tick seen wins the open
+    #[serde_as(as = "TimestampSecondsWithFrac<f64>")]
+    #[serde(default)]
+    pub open_datetime: DateTime<Utc>,
 }
 
 impl CandleData {
@@ -25,28 +32,187 @@ impl CandleData {
             low: price,
             datetime,
             volume,
+            tick_count: 1,
+            open_datetime: datetime,
         }
     }
 
+    /// Creates a flat candle without ticks, used to fill missing intervals
+    pub fn flat(datetime: DateTime<Utc>, price: f64) -> Self {
+        Self {
+            volume: 0.0,
+            tick_count: 0,
+            ..Self::new(datetime, price, 0.0)
+        }
+    }
+
+    /// Applies a subsequent tick. Prices are seeded by `new`, so zero and negative prices are regular values.
+    /// Out of order tick earlier than the open takes the open, but never the close or last tick date.
+    /// Tick at the same date as the open keeps the first price as open
     pub fn update(&mut self, datetime: DateTime<Utc>, price: f64, volume: f64) {
-        self.close = price;
         self.volume += volume;
-        self.datetime = datetime;
+        self.tick_count += 1;
+
+        if datetime >= self.datetime {
+            self.close = price;
+            self.datetime = datetime;
+        }
 
-        if self.open == 0.0 {
+        if datetime < self.open_datetime {
             self.open = price;
+            self.open_datetime = datetime;
         }
 
-        if self.high < price || self.high == 0.0 {
+        if self.high < price {
             self.high = price;
         }
 
-        if self.low > price || self.low == 0.0 {
+        if self.low > price {
             self.low = price;
         }
     }
 
+    /// Merges a later bar of the same interval: keeps the earliest open, takes close, extends high/low and sums volume and ticks
+    pub fn merge(&mut self, bar: &CandleData) {
+        if bar.open_datetime < self.open_datetime {
+            self.open = bar.open;
+            self.open_datetime = bar.open_datetime;
+        }
+
+        self.close = bar.close;
+        self.volume += bar.volume;
+        self.tick_count += bar.tick_count;
+        self.high = f64::max(self.high, bar.high);
+        self.low = f64::min(self.low, bar.low);
+
+        if bar.datetime > self.datetime {
+            self.datetime = bar.datetime;
+        }
+    }
+
+    /// Gets time passed since the last tick, negative if the candle is dated in the future
+    pub fn age(&self, now: DateTime<Utc>) -> Duration {
+        now - self.datetime
+    }
+
+    /// Checks if the last tick is older than `threshold`. Future dated candle isn't stale
+    pub fn is_stale(&self, now: DateTime<Utc>, threshold: Duration) -> bool {
+        self.age(now) > threshold
+    }
+
     pub fn get_candle_date(&self, candle_type: CandleType) -> DateTime<Utc> {
         candle_type.get_start_date(self.datetime)
     }
+
+    /// Gets [start, end) of the candle interval. End of the forming candle is in the future
+    pub fn interval(&self, candle_type: CandleType) -> (DateTime<Utc>, DateTime<Utc>) {
+        (candle_type.get_start_date(self.datetime), candle_type.get_end_date(self.datetime))
+    }
+
+    /// Serializes to TradingView lightweight-charts `{ time, open, high, low, close }` object,
+    /// time being unix seconds of the candle start. Volume isn't included
+    pub fn to_lightweight_chart_json(&self, candle_type: CandleType) -> String {
+        let (candle_date, _end) = self.interval(candle_type);
+
+        serde_json::to_string(&LightweightChartCandle::new(candle_date.timestamp(), self)).expect("candle serializes to json")
+    }
+}
+
+pub(crate) fn default_tick_count() -> u64 {
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::candle_data::CandleData;
+    use crate::models::candle_type::CandleType;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn earlier_tick_sets_open() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = CandleData::new(date + Duration::seconds(10), 2.0, 1.0);
+
+        candle.update(date + Duration::seconds(20), 3.0, 1.0);
+        assert_eq!(candle.open, 2.0);
+
+        candle.update(date + Duration::seconds(5), 1.5, 1.0);
+        assert_eq!(candle.open, 1.5);
+        assert_eq!(candle.open_datetime, date + Duration::seconds(5));
+        assert_eq!(candle.close, 3.0);
+        assert_eq!(candle.datetime, date + Duration::seconds(20));
+
+        candle.update(date + Duration::seconds(5), 1.75, 1.0);
+        assert_eq!(candle.open, 1.5);
+        assert_eq!(candle.close, 3.0);
+        assert_eq!(candle.datetime, date + Duration::seconds(20));
+        assert_eq!(candle.tick_count, 4);
+    }
+
+    #[tokio::test]
+    async fn deserialize_without_open_datetime() {
+        let json = r#"{"open":1.0,"close":2.0,"high":2.0,"low":1.0,"datetime":946684800.0,"volume":3.0}"#;
+
+        let candle: CandleData = serde_json::from_str(json).unwrap();
+
+        assert_eq!(candle.open, 1.0);
+        assert_eq!(candle.open_datetime.timestamp(), 0);
+        assert_eq!(candle.tick_count, 1);
+    }
+
+    #[tokio::test]
+    async fn age_and_is_stale() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let candle = CandleData::new(date, 1.0, 1.0);
+        let threshold = Duration::minutes(5);
+
+        assert_eq!(candle.age(date + Duration::minutes(1)), Duration::minutes(1));
+        assert!(!candle.is_stale(date + Duration::minutes(1), threshold));
+        assert!(candle.is_stale(date + Duration::minutes(6), threshold));
+        assert_eq!(candle.age(date - Duration::minutes(1)), Duration::minutes(-1));
+        assert!(!candle.is_stale(date - Duration::minutes(1), threshold));
+    }
+
+    #[tokio::test]
+    async fn interval() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 2, 10, 12, 30, 0).unwrap();
+        let candle = CandleData::new(date + Duration::seconds(15), 1.0, 1.0);
+
+        assert_eq!(candle.interval(CandleType::Minute), (date, date + Duration::minutes(1)));
+        assert_eq!(
+            candle.interval(CandleType::Month),
+            (
+                Utc.with_ymd_and_hms(2000, 2, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2000, 3, 1, 0, 0, 0).unwrap()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn zero_open_is_kept() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = CandleData::new(date, 0.0, 1.0);
+
+        candle.update(date + Duration::seconds(1), 0.5, 1.0);
+        candle.update(date + Duration::seconds(2), 0.25, 1.0);
+
+        assert_eq!((candle.open, candle.high, candle.low, candle.close), (0.0, 0.5, 0.0, 0.25));
+    }
+
+    #[tokio::test]
+    async fn descending_through_negative_prices() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = CandleData::new(date, 1.0, 1.0);
+
+        for (i, price) in [0.0, -0.5, -1.5, -1.0].into_iter().enumerate() {
+            candle.update(date + Duration::seconds(i as i64 + 1), price, 1.0);
+        }
+
+        assert_eq!((candle.open, candle.high, candle.low, candle.close), (1.0, 1.0, -1.5, -1.0));
+
+        let mut candle = CandleData::new(date, -2.0, 1.0);
+        candle.update(date + Duration::seconds(1), -3.0, 1.0);
+
+        assert_eq!((candle.open, candle.high, candle.low), (-2.0, -2.0, -3.0));
+    }
 }
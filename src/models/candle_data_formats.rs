@@ -0,0 +1,199 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Serialize, Deserialize};
+use serde_with::{serde_as, TimestampMilliSeconds};
+
+use super::candle_data::{default_tick_count, CandleData};
+
+/// `CandleData` with dates serialized as integer unix millis
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandleDataMillis {
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    pub datetime: DateTime<Utc>,
+    pub volume: f64,
+    #[serde(default = "default_tick_count")]
+    pub tick_count: u64,
+    #[serde_as(as = "TimestampMilliSeconds<i64>")]
+    #[serde(default)]
+    pub open_datetime: DateTime<Utc>,
+}
+
+/// `CandleData` with dates serialized as RFC3339 strings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandleDataRfc3339 {
+    pub open: f64,
+    pub close: f64,
+    pub high: f64,
+    pub low: f64,
+    #[serde(with = "rfc3339")]
+    pub datetime: DateTime<Utc>,
+    pub volume: f64,
+    #[serde(default = "default_tick_count")]
+    pub tick_count: u64,
+    #[serde(with = "rfc3339", default)]
+    pub open_datetime: DateTime<Utc>,
+}
+
+impl From<CandleData> for CandleDataMillis {
+    fn from(candle: CandleData) -> Self {
+        Self {
+            open: candle.open,
+            close: candle.close,
+            high: candle.high,
+            low: candle.low,
+            datetime: candle.datetime,
+            volume: candle.volume,
+            tick_count: candle.tick_count,
+            open_datetime: candle.open_datetime,
+        }
+    }
+}
+
+impl From<CandleDataMillis> for CandleData {
+    fn from(candle: CandleDataMillis) -> Self {
+        Self {
+            open: candle.open,
+            close: candle.close,
+            high: candle.high,
+            low: candle.low,
+            datetime: candle.datetime,
+            volume: candle.volume,
+            tick_count: candle.tick_count,
+            open_datetime: candle.open_datetime,
+        }
+    }
+}
+
+impl From<CandleData> for CandleDataRfc3339 {
+    fn from(candle: CandleData) -> Self {
+        Self {
+            open: candle.open,
+            close: candle.close,
+            high: candle.high,
+            low: candle.low,
+            datetime: candle.datetime,
+            volume: candle.volume,
+            tick_count: candle.tick_count,
+            open_datetime: candle.open_datetime,
+        }
+    }
+}
+
+impl From<CandleDataRfc3339> for CandleData {
+    fn from(candle: CandleDataRfc3339) -> Self {
+        Self {
+            open: candle.open,
+            close: candle.close,
+            high: candle.high,
+            low: candle.low,
+            datetime: candle.datetime,
+            volume: candle.volume,
+            tick_count: candle.tick_count,
+            open_datetime: candle.open_datetime,
+        }
+    }
+}
+
+/// Candle in TradingView lightweight-charts shape: `time` is unix seconds of the candle start
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightweightChartCandle {
+    pub time: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+impl LightweightChartCandle {
+    pub fn new(time: i64, candle: &CandleData) -> Self {
+        Self {
+            time,
+            open: candle.open,
+            high: candle.high,
+            low: candle.low,
+            close: candle.close,
+        }
+    }
+}
+
+/// Point of a lightweight-charts histogram series, used for volumes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LightweightChartValue {
+    pub time: i64,
+    pub value: f64,
+}
+
+mod rfc3339 {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(datetime: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&datetime.to_rfc3339_opts(SecondsFormat::AutoSi, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+        let value = String::deserialize(deserializer)?;
+
+        DateTime::parse_from_rfc3339(&value)
+            .map(|datetime| datetime.with_timezone(&Utc))
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::candle_data::CandleData;
+    use crate::models::candle_data_formats::{CandleDataMillis, CandleDataRfc3339};
+    use crate::models::candle_type::CandleType;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    fn create_candle() -> CandleData {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let mut candle = CandleData::new(date, 1.0, 1.0);
+        candle.update(date + Duration::milliseconds(1500), 2.0, 1.0);
+
+        candle
+    }
+
+    #[tokio::test]
+    async fn serialize_millis() {
+        let candle = create_candle();
+
+        let json = serde_json::to_string(&CandleDataMillis::from(candle.clone())).unwrap();
+        let restored: CandleDataMillis = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"open":1.0,"close":2.0,"high":2.0,"low":1.0,"datetime":946684801500,"volume":2.0,"tick_count":2,"open_datetime":946684800000}"#
+        );
+        assert_eq!(CandleData::from(restored), candle);
+    }
+
+    #[tokio::test]
+    async fn serialize_rfc3339() {
+        let candle = create_candle();
+
+        let json = serde_json::to_string(&CandleDataRfc3339::from(candle.clone())).unwrap();
+        let restored: CandleDataRfc3339 = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            json,
+            r#"{"open":1.0,"close":2.0,"high":2.0,"low":1.0,"datetime":"2000-01-01T00:00:01.500Z","volume":2.0,"tick_count":2,"open_datetime":"2000-01-01T00:00:00Z"}"#
+        );
+        assert_eq!(CandleData::from(restored), candle);
+    }
+
+    #[tokio::test]
+    async fn lightweight_chart_json() {
+        let candle = create_candle();
+
+        assert_eq!(
+            candle.to_lightweight_chart_json(CandleType::Minute),
+            r#"{"time":946684800,"open":1.0,"high":2.0,"low":1.0,"close":2.0}"#
+        );
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use super::candle_type::CandleType;
+
+/// Candle of arbitrary fixed length, for intervals without a `CandleType` variant (e.g. 10m, 45m).
+/// Buckets are anchored to Unix epoch: a candle starts at `timestamp - timestamp % seconds`.
+///
+/// `CandleType` stays a closed `#[repr(i32)]` set, since it's serialized as an integer.
+/// `CandlesCache` keeps storing candles of its `CandleType`s only, and interval candles are
+/// aggregated on read from the coarsest cached type dividing the interval, see `CandlesCache::get_interval_candles`
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CandleInterval {
+    seconds: u32,
+}
+
+impl CandleInterval {
+    /// Gets interval of specified length, None for zero
+    pub fn from_seconds(seconds: u32) -> Option<Self> {
+        (seconds > 0).then_some(Self { seconds })
+    }
+
+    /// Gets interval of specified length, None for zero or overflowing minutes
+    pub fn from_minutes(minutes: u32) -> Option<Self> {
+        Self::from_seconds(minutes.checked_mul(60)?)
+    }
+
+    /// Gets interval of the candle type if its buckets are epoch anchored and have fixed length.
+    /// Month, Week and Session have none
+    pub fn from_candle_type(candle_type: &CandleType) -> Option<Self> {
+        match candle_type {
+            CandleType::Month | CandleType::Week | CandleType::Session => None,
+            _ => Self::from_seconds(candle_type.get_duration(DateTime::<Utc>::default()).num_seconds() as u32),
+        }
+    }
+
+    pub fn get_seconds(&self) -> u32 {
+        self.seconds
+    }
+
+    /// Checks every candle of `other` interval lies within a single candle of this one
+    pub fn is_multiple_of(&self, other: &CandleInterval) -> bool {
+        self.seconds.is_multiple_of(other.seconds)
+    }
+
+    pub fn get_start_date(&self, datetime: DateTime<Utc>) -> DateTime<Utc> {
+        let timestamp_sec = datetime.timestamp();
+
+        Utc.timestamp_opt(timestamp_sec - timestamp_sec.rem_euclid(self.seconds as i64), 0)
+            .unwrap()
+    }
+
+    pub fn get_duration(&self) -> Duration {
+        Duration::seconds(self.seconds as i64)
+    }
+
+    pub fn get_end_date(&self, datetime: DateTime<Utc>) -> DateTime<Utc> {
+        self.get_start_date(datetime) + self.get_duration()
+    }
+
+    /// Gets count of candles from the one containing `datetime_from` to the one containing `datetime_to`
+    pub fn get_dates_count(&self, datetime_from: DateTime<Utc>, datetime_to: DateTime<Utc>) -> usize {
+        let from = self.get_start_date(datetime_from);
+        let to = self.get_end_date(datetime_to);
+
+        ((to - from).num_seconds() / self.seconds as i64).max(0) as usize
+    }
+
+    /// Iterates candle start dates between specified dates in ascending order
+    pub fn iter_start_dates(
+        &self,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
+        let date_to = self.get_start_date(datetime_to);
+
+        std::iter::successors(Some(self.get_start_date(datetime_from)), move |last_date| {
+            (*last_date < date_to).then(|| *last_date + self.get_duration())
+        })
+    }
+
+    /// Gets candle start dates between specified dates in ascending order
+    pub fn get_start_dates_ordered(&self, datetime_from: DateTime<Utc>, datetime_to: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        self.iter_start_dates(datetime_from, datetime_to).collect()
+    }
+}
+
+/// Shortest exact form: 45s, 10m, 2h or 3d
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.seconds {
+            seconds if seconds % 86400 == 0 => write!(f, "{}d", seconds / 86400),
+            seconds if seconds % 3600 == 0 => write!(f, "{}h", seconds / 3600),
+            seconds if seconds % 60 == 0 => write!(f, "{}m", seconds / 60),
+            seconds => write!(f, "{}s", seconds),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCandleIntervalError(pub String);
+
+impl fmt::Display for ParseCandleIntervalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown candle interval {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseCandleIntervalError {}
+
+/// Parses positive count with s, m, h or d unit, e.g. 10m
+impl FromStr for CandleInterval {
+    type Err = ParseCandleIntervalError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || ParseCandleIntervalError(value.to_string());
+        let unit_index = value.len().checked_sub(1).filter(|index| value.is_char_boundary(*index)).ok_or_else(error)?;
+        let (count, unit) = value.split_at(unit_index);
+        let count: u32 = count.parse().map_err(|_| error())?;
+        let unit_seconds = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 3600,
+            "d" => 86400,
+            _ => return Err(error()),
+        };
+
+        count
+            .checked_mul(unit_seconds)
+            .and_then(CandleInterval::from_seconds)
+            .ok_or_else(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::candle_interval::CandleInterval;
+    use crate::models::candle_type::CandleType;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn five_and_fifteen_minutes_across_hour() {
+        let hour: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 11, 0, 0).unwrap();
+        let from = hour - Duration::minutes(17);
+        let to = hour + Duration::minutes(17);
+
+        for minutes in [5, 15] {
+            let interval = CandleInterval::from_minutes(minutes).unwrap();
+            let step = Duration::minutes(minutes as i64);
+
+            assert_eq!(interval.get_start_date(hour - Duration::seconds(1)), hour - step);
+            assert_eq!(interval.get_start_date(hour), hour);
+            assert_eq!(interval.get_end_date(hour - Duration::seconds(1)), hour);
+            assert_eq!(interval.get_start_date(hour + step - Duration::seconds(1)), hour);
+
+            let dates = interval.get_start_dates_ordered(from, to);
+
+            assert_eq!(dates.len(), interval.get_dates_count(from, to));
+            assert!(dates.contains(&hour));
+            assert!(dates.iter().all(|date| date.timestamp() % (minutes as i64 * 60) == 0));
+        }
+
+        assert_eq!(CandleInterval::from_minutes(5).unwrap().get_dates_count(from, to), 8);
+        assert_eq!(CandleInterval::from_minutes(15).unwrap().get_dates_count(from, to), 4);
+    }
+
+    #[tokio::test]
+    async fn seven_minutes_cross_midnight() {
+        let interval = CandleInterval::from_minutes(7).unwrap();
+        let midnight: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 2, 0, 0, 0).unwrap();
+        let from = midnight - Duration::minutes(20);
+        let to = midnight + Duration::minutes(20);
+
+        let dates = interval.get_start_dates_ordered(from, to);
+
+        assert_eq!(dates.first().unwrap().timestamp() % 420, 0);
+        assert!(dates.windows(2).all(|pair| pair[1] - pair[0] == Duration::minutes(7)));
+        assert!(!dates.contains(&midnight));
+        assert!(dates[0] <= from && *dates.last().unwrap() <= to && to < interval.get_end_date(to));
+        assert_eq!(interval.get_dates_count(from, to), dates.len());
+
+        let bucket = interval.get_start_date(midnight);
+        assert!(bucket < midnight && midnight < interval.get_end_date(midnight));
+    }
+
+    #[tokio::test]
+    async fn matches_candle_types() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 13, 47, 12).unwrap();
+
+        for candle_type in [CandleType::Minute, CandleType::FifteenMinutes, CandleType::FourHours, CandleType::ThreeDays] {
+            let interval = CandleInterval::from_candle_type(&candle_type).unwrap();
+
+            assert_eq!(interval.get_start_date(date), candle_type.get_start_date(date));
+            assert_eq!(interval.get_end_date(date), candle_type.get_end_date(date));
+        }
+
+        assert_eq!(CandleInterval::from_candle_type(&CandleType::Week), None);
+        assert_eq!(CandleInterval::from_candle_type(&CandleType::Month), None);
+    }
+
+    #[tokio::test]
+    async fn parse_and_display() {
+        for (value, seconds) in [("45s", 45), ("10m", 600), ("90m", 5400), ("2h", 7200), ("3d", 259200)] {
+            let interval: CandleInterval = value.parse().unwrap();
+
+            assert_eq!(interval.get_seconds(), seconds);
+            assert_eq!(interval.to_string(), value);
+        }
+
+        assert_eq!("120m".parse::<CandleInterval>().unwrap().to_string(), "2h");
+
+        for value in ["", "m", "0m", "-5m", "5x", "5", "99999999d"] {
+            assert!(value.parse::<CandleInterval>().is_err(), "{value}");
+        }
+
+        assert_eq!(CandleInterval::from_seconds(0), None);
+    }
+}
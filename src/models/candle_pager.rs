@@ -1,6 +1,16 @@
 use crate::models::candle::BidAskCandle;
 use crate::models::candle_type::CandleType;
-use chrono::{DateTime, TimeZone, Utc};
+use crate::utils::time::datetime_from_millis;
+use chrono::{DateTime, Duration, Utc};
+
+/// Order candles are paged in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageDirection {
+    /// Oldest first, from `from_date` toward `to_date`
+    Ascending,
+    /// Newest first, from `to_date` back toward `from_date`
+    Descending,
+}
 
 #[derive(Debug)]
 pub struct CandlePager {
@@ -11,9 +21,11 @@ pub struct CandlePager {
     page_id: Option<String>,
     limit: usize,
     last_item_no: usize,
+    direction: PageDirection,
 }
 
 impl CandlePager {
+    /// Both dates are inclusive, so equal dates give a single page with the candle containing them
     pub fn new(
         instrument: String,
         candle_type: CandleType,
@@ -37,9 +49,17 @@ impl CandlePager {
             page_id,
             limit,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         }
     }
 
+    /// Sets paging direction. In `Descending` direction page id is the date of the newest candle of the page
+    pub fn with_direction(mut self, direction: PageDirection) -> Self {
+        self.direction = direction;
+
+        self
+    }
+
     pub fn get_instrument(&self) -> &str {
         &self.instrument
     }
@@ -49,32 +69,128 @@ impl CandlePager {
     }
 
     pub fn get_next_page_id(&self) -> Option<String> {
+        if self.direction == PageDirection::Descending {
+            if self.limit == 0 {
+                return None;
+            }
+
+            // the cursor walks to_date back, so the range can't tell if there is one page only
+            let remaining_item_count = self.limit - self.last_item_no;
+            let mut to_date = self.get_descending_cursor()?;
+
+            match self.candle_type {
+                CandleType::Month => {
+                    for _ in 0..remaining_item_count {
+                        to_date = self.get_previous_date(to_date);
+                    }
+                }
+                _ => {
+                    let candle_duration = self.candle_type.get_duration(to_date);
+                    to_date = self.candle_type.get_start_date(to_date - candle_duration * remaining_item_count as i32);
+                }
+            }
+
+            if to_date < self.from_date {
+                return None;
+            }
+
+            return Some(to_date.timestamp_millis().to_string());
+        }
+
         let total_items_count = self
             .candle_type
             .get_dates_count(self.from_date, self.to_date);
 
-        if self.limit > total_items_count {
+        if self.limit == 0 || self.limit > total_items_count {
             // there is only one page
             return None;
         }
 
         let remaining_item_count = self.limit - self.last_item_no;
-        let candle_duration = self.candle_type.get_duration(self.from_date);
-        let total_duration = candle_duration * remaining_item_count as i32;
-        let from_date = self.from_date + total_duration + candle_duration;
+        let from_date = match self.candle_type {
+            CandleType::Month => {
+                let mut from_date = self.from_date;
+
+                for _ in 0..remaining_item_count {
+                    from_date = self.candle_type.get_end_date(from_date);
+                }
 
-        if from_date > self.to_date {
+                from_date
+            }
+            _ => {
+                let candle_duration = self.candle_type.get_duration(self.from_date);
+
+                self.from_date + candle_duration * remaining_item_count as i32
+            }
+        };
+        let from_date = self.candle_type.get_start_date(from_date);
+
+        if from_date >= self.get_end_date() {
             return None;
         }
 
         Some(from_date.timestamp_millis().to_string())
     }
 
+    /// Gets ids of all candles of the remaining date range in paging direction ignoring limit and paging
+    pub fn all_candle_ids(&self) -> Vec<String> {
+        if self.direction == PageDirection::Descending {
+            let Some(to_date) = self.get_descending_cursor().filter(|date| *date >= self.from_date) else {
+                return vec![];
+            };
+            let mut ids: Vec<String> = self
+                .candle_type
+                .iter_start_dates(self.from_date, to_date)
+                .map(|date| BidAskCandle::generate_id(&self.instrument, &self.candle_type, date))
+                .collect();
+            ids.reverse();
+
+            return ids;
+        }
+
+        let end_date = self.get_end_date();
+
+        self.candle_type
+            .iter_start_dates(self.from_date, end_date)
+            .take_while(|date| *date < end_date)
+            .map(|date| BidAskCandle::generate_id(&self.instrument, &self.candle_type, date))
+            .collect()
+    }
+
+    /// Gets exclusive end of the date range
+    fn get_end_date(&self) -> DateTime<Utc> {
+        // move_candle_id turns inclusive to_date into the end of its candle
+        if self.last_item_no == 0 {
+            self.candle_type.get_end_date(self.to_date)
+        } else {
+            self.to_date
+        }
+    }
+
+    /// Gets start of the newest candle not yet returned in `Descending` direction, None for invalid page id
+    fn get_descending_cursor(&self) -> Option<DateTime<Utc>> {
+        if self.last_item_no > 0 {
+            return Some(self.to_date);
+        }
+
+        match self.page_id.as_ref() {
+            Some(page_id) => {
+                let date = parse_page_id(page_id)?;
+
+                Some(self.candle_type.get_start_date(date.min(self.to_date)))
+            }
+            None => Some(self.to_date),
+        }
+    }
+
+    /// Gets start of the candle right before the candle starting at `date`
+    fn get_previous_date(&self, date: DateTime<Utc>) -> DateTime<Utc> {
+        self.candle_type.get_start_date(date - Duration::seconds(1))
+    }
+
     pub fn move_page_id(&mut self) -> Option<String> {
         let next_page_id = self.get_next_page_id()?;
-        let date = Utc
-            .timestamp_millis_opt(next_page_id.parse().unwrap())
-            .unwrap();
+        let date = parse_page_id(&next_page_id)?;
         self.from_date = date;
 
         Some(next_page_id)
@@ -85,14 +201,27 @@ impl CandlePager {
             return None;
         }
 
+        if self.direction == PageDirection::Descending {
+            let to_date = self.get_descending_cursor()?;
+
+            if to_date < self.from_date {
+                return None;
+            }
+
+            let id = BidAskCandle::generate_id(&self.instrument, &self.candle_type, to_date);
+            self.last_item_no += 1;
+            self.to_date = self.get_previous_date(to_date);
+
+            return Some(id);
+        }
+
         if self.last_item_no == 0 {
             self.from_date = self.candle_type.get_start_date(self.from_date);
             self.to_date = self.candle_type.get_end_date(self.to_date);
-        }
 
-        if let Some(page_id) = self.page_id.as_ref() {
-            let page_id = page_id.parse::<i64>().expect("Failed to parse page_id");
-            self.from_date = Utc.timestamp_millis_opt(page_id).unwrap()
+            if let Some(page_id) = self.page_id.as_ref() {
+                self.from_date = parse_page_id(page_id)?;
+            }
         }
 
         if self.from_date >= self.to_date {
@@ -112,11 +241,27 @@ impl CandlePager {
             return vec![];
         }
 
+        if self.direction == PageDirection::Descending {
+            let Some(mut to_date) = self.get_descending_cursor() else {
+                return vec![];
+            };
+            let mut ids = Vec::new();
+
+            while ids.len() < self.limit - self.last_item_no && to_date >= self.from_date {
+                ids.push(BidAskCandle::generate_id(&self.instrument, &self.candle_type, to_date));
+                to_date = self.get_previous_date(to_date);
+            }
+
+            return ids;
+        }
+
         let mut from_date = self.candle_type.get_start_date(self.from_date);
 
         if let Some(page_id) = self.page_id.as_ref() {
-            let page_id = page_id.parse::<i64>().expect("Failed to parse page_id");
-            from_date = Utc.timestamp_millis_opt(page_id).unwrap()
+            match parse_page_id(page_id) {
+                Some(date) => from_date = date,
+                None => return vec![],
+            }
         }
 
         let to_date = self.candle_type.get_end_date(self.to_date);
@@ -147,9 +292,15 @@ impl CandlePager {
     }
 }
 
+/// Parses page id of epoch millis, None if it isn't a number or a valid date
+fn parse_page_id(page_id: &str) -> Option<DateTime<Utc>> {
+    datetime_from_millis(page_id.parse().ok()?).ok()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::models::candle_pager::CandlePager;
+    use crate::models::candle::BidAskCandle;
+    use crate::models::candle_pager::{CandlePager, PageDirection};
     use crate::models::candle_type::CandleType;
     use chrono::{DateTime, Duration, TimeZone, Utc};
 
@@ -163,12 +314,13 @@ mod tests {
             page_id: None,
             limit: 2,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
-        assert_eq!(pager.move_candle_id(), Some("0test946684800".to_string()));
+        assert_eq!(pager.move_candle_id(), Some("0:test:946684800".to_string()));
         assert_eq!(1, pager.last_item_no);
 
-        assert_eq!(pager.move_candle_id(), Some("0test946684860".to_string()));
+        assert_eq!(pager.move_candle_id(), Some("0:test:946684860".to_string()));
         assert_eq!(2, pager.last_item_no);
 
         let id = pager.move_candle_id();
@@ -185,14 +337,15 @@ mod tests {
             page_id: None,
             limit: 3,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
-        assert_eq!(pager.get_next_page_id(), Some("946685040000".to_string()));
+        assert_eq!(pager.get_next_page_id(), Some("946684980000".to_string()));
         _ = pager.move_candle_id();
-        assert_eq!(pager.get_next_page_id(), Some("946685040000".to_string()));
+        assert_eq!(pager.get_next_page_id(), Some("946684980000".to_string()));
         _ = pager.move_candle_id();
         _ = pager.move_candle_id();
-        assert_eq!(pager.get_next_page_id(), Some("946685040000".to_string()));
+        assert_eq!(pager.get_next_page_id(), Some("946684980000".to_string()));
     }
 
     #[tokio::test]
@@ -205,6 +358,7 @@ mod tests {
             page_id: None,
             limit: 5,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -226,6 +380,7 @@ mod tests {
             page_id: None,
             limit: 1500,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -248,6 +403,7 @@ mod tests {
             page_id: None,
             limit: 1500,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -267,6 +423,7 @@ mod tests {
             page_id: None,
             limit: 10000,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -290,12 +447,13 @@ mod tests {
             page_id: None,
             limit: 10000,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
         let mut count = 0;
 
-        while let Some(_) = pager.move_candle_id() {
+        while pager.move_candle_id().is_some() {
             count += 1;
         }
 
@@ -317,6 +475,7 @@ mod tests {
             page_id: None,
             limit: 10000,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -326,14 +485,14 @@ mod tests {
         while let Some(id) = pager.move_candle_id() {
             count += 1;
             last_move_date = Utc
-                .timestamp_millis_opt(id.replace("0BTCUSDT", "").parse::<i64>().unwrap() * 1000)
+                .timestamp_millis_opt(id.replace("0:BTCUSDT:", "").parse::<i64>().unwrap() * 1000)
                 .unwrap();
         }
 
         let last_get_date = Utc
             .timestamp_millis_opt(
                 ids[ids.len() - 1]
-                    .replace("0BTCUSDT", "")
+                    .replace("0:BTCUSDT:", "")
                     .parse::<i64>()
                     .unwrap()
                     * 1000,
@@ -359,6 +518,7 @@ mod tests {
             page_id: None,
             limit: 10000,
             last_item_no: 0,
+            direction: PageDirection::Ascending,
         };
 
         let ids = pager.get_page_candle_ids();
@@ -368,14 +528,14 @@ mod tests {
         while let Some(id) = pager.move_candle_id() {
             count += 1;
             last_move_date = Utc
-                .timestamp_millis_opt(id.replace("0BTCUSDT", "").parse::<i64>().unwrap() * 1000)
+                .timestamp_millis_opt(id.replace("0:BTCUSDT:", "").parse::<i64>().unwrap() * 1000)
                 .unwrap();
         }
 
         let last_get_date = Utc
             .timestamp_millis_opt(
                 ids[ids.len() - 1]
-                    .replace("0BTCUSDT", "")
+                    .replace("0:BTCUSDT:", "")
                     .parse::<i64>()
                     .unwrap()
                     * 1000,
@@ -385,4 +545,179 @@ mod tests {
         assert_eq!(last_move_date, last_get_date);
         assert_eq!(ids.len(), count);
     }
+
+    #[tokio::test]
+    async fn same_from_and_to() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 1, 0, 0, 0).unwrap();
+
+        for candle_type in [CandleType::Minute, CandleType::Hour, CandleType::Month] {
+            for limit in [1, 10] {
+                let mut pager = CandlePager::new("BTCUSDT".to_string(), candle_type.clone(), date, date, None, limit);
+                let id = BidAskCandle::generate_id("BTCUSDT", &candle_type, date);
+
+                assert_eq!(pager.get_page_candle_ids(), vec![id.clone()]);
+                assert_eq!(pager.get_next_page_id(), None);
+                assert_eq!(pager.move_candle_id(), Some(id));
+                assert_eq!(pager.move_candle_id(), None);
+                assert_eq!(pager.get_next_page_id(), None);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn zero_limit() {
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 12, 1, 0, 0, 0).unwrap();
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, date, date, None, 0);
+
+        assert!(pager.get_page_candle_ids().is_empty());
+        assert_eq!(pager.get_next_page_id(), None);
+        assert_eq!(pager.move_candle_id(), None);
+    }
+
+    #[tokio::test]
+    async fn next_page_id_points_to_next_candle() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2001, 12, 1, 0, 0, 0).unwrap();
+
+        for (candle_type, expected_next) in [
+            (CandleType::Minute, from + Duration::minutes(3)),
+            (CandleType::Month, Utc.with_ymd_and_hms(2000, 4, 1, 0, 0, 0).unwrap()),
+        ] {
+            let pager = CandlePager::new("BTCUSDT".to_string(), candle_type.clone(), from, to, None, 3);
+            let page_id = pager.get_next_page_id().unwrap();
+
+            assert_eq!(page_id, expected_next.timestamp_millis().to_string());
+
+            let mut next_pager = CandlePager::new("BTCUSDT".to_string(), candle_type.clone(), from, to, Some(page_id), 3);
+            let ids = next_pager.get_page_candle_ids();
+            let expected_ids: Vec<String> = (0..3)
+                .scan(expected_next, |date, _| {
+                    let id = BidAskCandle::generate_id("BTCUSDT", &candle_type, *date);
+                    *date = candle_type.get_end_date(*date);
+                    Some(id)
+                })
+                .collect();
+
+            assert_eq!(ids, expected_ids);
+            assert_eq!(next_pager.move_candle_id(), Some(expected_ids[0].clone()));
+            assert_eq!(next_pager.move_candle_id(), Some(expected_ids[1].clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn next_page_id_past_to_date() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        let pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, from + Duration::minutes(2), None, 3);
+        assert_eq!(pager.get_next_page_id(), None);
+
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, from + Duration::minutes(5), None, 3);
+        let page_id = pager.move_page_id();
+
+        assert_eq!(page_id, Some((from + Duration::minutes(3)).timestamp_millis().to_string()));
+        assert_eq!(pager.get_next_page_id(), None);
+    }
+
+    #[tokio::test]
+    async fn out_of_range_page_id() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let page_id = Some(i64::MAX.to_string());
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, from, page_id, 10);
+
+        assert!(pager.get_page_candle_ids().is_empty());
+        assert_eq!(pager.move_candle_id(), None);
+    }
+
+    #[tokio::test]
+    async fn malformed_page_id() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let to = from + Duration::minutes(5);
+
+        for direction in [PageDirection::Ascending, PageDirection::Descending] {
+            for page_id in ["garbage", "", "1.5"] {
+                let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, to, Some(page_id.to_string()), 3)
+                    .with_direction(direction);
+
+                assert!(pager.get_page_candle_ids().is_empty());
+                assert_eq!(pager.move_candle_id(), None);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn all_candle_ids() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 30).unwrap();
+
+        for (candle_type, to) in [
+            (CandleType::Minute, from + Duration::hours(1)),
+            (CandleType::Month, Utc.with_ymd_and_hms(2001, 3, 5, 0, 0, 0).unwrap()),
+        ] {
+            let pager = CandlePager::new("BTCUSDT".to_string(), candle_type.clone(), from, to, None, 10);
+            let ids = pager.all_candle_ids();
+
+            assert_eq!(ids.len(), candle_type.get_dates_count(from, to));
+            assert_eq!(ids[0], BidAskCandle::generate_id("BTCUSDT", &candle_type, from));
+            assert_eq!(ids[ids.len() - 1], BidAskCandle::generate_id("BTCUSDT", &candle_type, to));
+        }
+    }
+
+    #[tokio::test]
+    async fn descending_pages() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+        let to = from + Duration::minutes(6);
+        let id = |date: DateTime<Utc>| BidAskCandle::generate_id("BTCUSDT", &CandleType::Minute, date);
+
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, to, None, 3)
+            .with_direction(PageDirection::Descending);
+        let expected = vec![id(to), id(to - Duration::minutes(1)), id(to - Duration::minutes(2))];
+        let page_id = pager.get_next_page_id().unwrap();
+
+        assert_eq!(pager.get_page_candle_ids(), expected);
+        assert_eq!(page_id, (to - Duration::minutes(3)).timestamp_millis().to_string());
+
+        for expected_id in expected.iter() {
+            assert_eq!(pager.move_candle_id().as_ref(), Some(expected_id));
+        }
+
+        assert_eq!(pager.move_candle_id(), None);
+        assert_eq!(pager.get_next_page_id(), Some(page_id.clone()));
+
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, to, Some(page_id), 3)
+            .with_direction(PageDirection::Descending);
+        let expected = vec![id(from + Duration::minutes(3)), id(from + Duration::minutes(2)), id(from + Duration::minutes(1))];
+
+        assert_eq!(pager.get_page_candle_ids(), expected);
+        assert_eq!(pager.all_candle_ids().len(), 4);
+
+        let last_page_id = pager.get_next_page_id().unwrap();
+        assert_eq!(last_page_id, from.timestamp_millis().to_string());
+
+        while pager.move_candle_id().is_some() {}
+
+        assert_eq!(pager.get_next_page_id(), Some(last_page_id.clone()));
+
+        let pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Minute, from, to, Some(last_page_id), 3)
+            .with_direction(PageDirection::Descending);
+
+        assert_eq!(pager.get_page_candle_ids(), vec![id(from)]);
+        assert_eq!(pager.get_next_page_id(), None);
+    }
+
+    #[tokio::test]
+    async fn descending_months() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 15, 0, 0, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 4, 3, 0, 0, 0).unwrap();
+        let mut pager = CandlePager::new("BTCUSDT".to_string(), CandleType::Month, from, to, None, 2)
+            .with_direction(PageDirection::Descending);
+        let month = |month| BidAskCandle::generate_id("BTCUSDT", &CandleType::Month, Utc.with_ymd_and_hms(2000, month, 1, 0, 0, 0).unwrap());
+
+        assert_eq!(pager.get_page_candle_ids(), vec![month(4), month(3)]);
+        assert_eq!(
+            pager.get_next_page_id(),
+            Some(Utc.with_ymd_and_hms(2000, 2, 1, 0, 0, 0).unwrap().timestamp_millis().to_string())
+        );
+        assert_eq!(pager.move_candle_id(), Some(month(4)));
+        assert_eq!(pager.move_candle_id(), Some(month(3)));
+        assert_eq!(pager.move_candle_id(), None);
+    }
 }
@@ -0,0 +1,65 @@
+use super::candle_data::CandleData;
+use crate::utils::time::{datetime_from_millis, TimeError};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CandlePriceProto {
+    #[prost(int64, tag = "1")]
+    pub datetime_ms: i64,
+    #[prost(double, tag = "2")]
+    pub open: f64,
+    #[prost(double, tag = "3")]
+    pub close: f64,
+    #[prost(double, tag = "4")]
+    pub high: f64,
+    #[prost(double, tag = "5")]
+    pub low: f64,
+    #[prost(double, tag = "6")]
+    pub volume: f64,
+    #[prost(int64, tag = "7")]
+    pub open_datetime_ms: i64,
+    /// Absent in messages of older producers, unlike 0 of candles without ticks
+    #[prost(uint64, optional, tag = "8")]
+    pub tick_count: Option<u64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct CandlePricesProto {
+    #[prost(int32, tag = "1")]
+    pub candle_type: i32,
+    #[prost(message, repeated, tag = "2")]
+    pub candles: Vec<CandlePriceProto>,
+}
+
+impl From<&CandleData> for CandlePriceProto {
+    fn from(candle: &CandleData) -> Self {
+        Self {
+            datetime_ms: candle.datetime.timestamp_millis(),
+            open: candle.open,
+            close: candle.close,
+            high: candle.high,
+            low: candle.low,
+            volume: candle.volume,
+            open_datetime_ms: candle.open_datetime.timestamp_millis(),
+            tick_count: Some(candle.tick_count),
+        }
+    }
+}
+
+/// Fails on out of range millis instead of defaulting them to epoch
+impl TryFrom<&CandlePriceProto> for CandleData {
+    type Error = TimeError;
+
+    fn try_from(proto: &CandlePriceProto) -> Result<Self, Self::Error> {
+        Ok(Self {
+            open: proto.open,
+            close: proto.close,
+            high: proto.high,
+            low: proto.low,
+            datetime: datetime_from_millis(proto.datetime_ms)?,
+            volume: proto.volume,
+            // messages of older producers have no tick count, each candle had at least one tick
+            tick_count: proto.tick_count.unwrap_or(1),
+            open_datetime: datetime_from_millis(proto.open_datetime_ms)?,
+        })
+    }
+}
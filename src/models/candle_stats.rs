@@ -0,0 +1,10 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandleStats {
+    pub count: usize,
+    pub total_volume: f64,
+    pub average_close: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Population standard deviation of closes
+    pub close_std_dev: f64,
+}
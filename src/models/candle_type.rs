@@ -1,6 +1,8 @@
 use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
 
-use chrono::{DateTime, Datelike, Utc};
+use chrono::{DateTime, Datelike, FixedOffset, Utc};
 use chrono::{Duration, TimeZone};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 use serde_repr::{Deserialize_repr, Serialize_repr};
@@ -33,11 +35,68 @@ pub enum CandleType {
     SixHours = 10,
     EightHours = 11,
     TwelveHours = 12,
+    /// Doesn't divide a calendar week or month, so buckets are anchored to Unix epoch:
+    /// they run continuously across day boundaries.
+    /// Compatibility: buckets were 7-day aligned before, so start dates and ids of stored candles change
     ThreeDays = 13,
+    /// Anchored to Unix epoch (Thursday), unlike Monday-aligned `Week`.
+    /// Compatibility: buckets were 12-day aligned before, so start dates and ids of stored candles change
     SevenDays = 14,
+    /// Trading session candle. Sessions are configured on the cache,
+    /// without them it's aligned like Day
+    Session = 15,
+    /// Calendar week starting Monday 00:00 UTC
+    Week = 16,
 }
 
 impl CandleType {
+    /// Parses MetaTrader-style interval code (M1, H4, D1, W1, MN, ...)
+    pub fn from_protocol_code(code: &str) -> Option<CandleType> {
+        match code {
+            "M1" => Some(CandleType::Minute),
+            "M3" => Some(CandleType::ThreeMinutes),
+            "M5" => Some(CandleType::FiveMinutes),
+            "M15" => Some(CandleType::FifteenMinutes),
+            "M30" => Some(CandleType::ThirtyMinutes),
+            "H1" => Some(CandleType::Hour),
+            "H2" => Some(CandleType::TwoHours),
+            "H4" => Some(CandleType::FourHours),
+            "H6" => Some(CandleType::SixHours),
+            "H8" => Some(CandleType::EightHours),
+            "H12" => Some(CandleType::TwelveHours),
+            "D1" => Some(CandleType::Day),
+            "W1" => Some(CandleType::Week),
+            "MN" | "MN1" => Some(CandleType::Month),
+            _ => None,
+        }
+    }
+
+    /// Gets MetaTrader-style interval code. ThreeDays, SevenDays and other
+    /// non-MetaTrader intervals have no such code
+    pub fn to_protocol_code(&self) -> Option<&'static str> {
+        let code = match self {
+            CandleType::Minute => "M1",
+            CandleType::ThreeMinutes => "M3",
+            CandleType::FiveMinutes => "M5",
+            CandleType::FifteenMinutes => "M15",
+            CandleType::ThirtyMinutes => "M30",
+            CandleType::Hour => "H1",
+            CandleType::TwoHours => "H2",
+            CandleType::FourHours => "H4",
+            CandleType::SixHours => "H6",
+            CandleType::EightHours => "H8",
+            CandleType::TwelveHours => "H12",
+            CandleType::Day => "D1",
+            CandleType::Week => "W1",
+            CandleType::Month => "MN",
+            CandleType::ThreeDays
+            | CandleType::SevenDays
+            | CandleType::Session => return None,
+        };
+
+        Some(code)
+    }
+
     pub fn get_start_date(&self, datetime: DateTime<Utc>) -> DateTime<Utc> {
         let timestamp_sec = datetime.timestamp();
 
@@ -53,11 +112,9 @@ impl CandleType {
                 .unwrap(),
             CandleType::Month => {
                 let date = Utc.timestamp_millis_opt(timestamp_sec * 1000).unwrap();
-                let start_of_month: DateTime<Utc> = Utc
-                    .with_ymd_and_hms(date.year(), date.month(), 1, 0, 0, 0)
-                    .unwrap();
 
-                start_of_month
+                get_month_start(date.year(), date.month())
+                    .unwrap_or_else(|| CandleType::Day.get_start_date(datetime))
             }
             CandleType::ThreeMinutes => Utc
                 .timestamp_millis_opt((timestamp_sec - timestamp_sec % 180) * 1000)
@@ -87,10 +144,14 @@ impl CandleType {
                 .timestamp_millis_opt((timestamp_sec - timestamp_sec % 43200) * 1000)
                 .unwrap(),
             CandleType::ThreeDays => Utc
-                .timestamp_millis_opt((timestamp_sec - timestamp_sec % 604800) * 1000)
+                .timestamp_millis_opt((timestamp_sec - timestamp_sec % 259200) * 1000)
                 .unwrap(),
             CandleType::SevenDays => Utc
-                .timestamp_millis_opt((timestamp_sec - timestamp_sec % 1036800) * 1000)
+                .timestamp_millis_opt((timestamp_sec - timestamp_sec % 604800) * 1000)
+                .unwrap(),
+            CandleType::Session => CandleType::Day.get_start_date(datetime),
+            CandleType::Week => Utc
+                .timestamp_millis_opt((timestamp_sec - (timestamp_sec - FIRST_MONDAY_TIMESTAMP).rem_euclid(604800)) * 1000)
                 .unwrap(),
         }
     }
@@ -100,20 +161,39 @@ impl CandleType {
         datetime_from: DateTime<Utc>,
         datetime_to: DateTime<Utc>,
     ) -> HashSet<DateTime<Utc>> {
-        let mut dates = HashSet::new();
+        self.get_start_dates_ordered(datetime_from, datetime_to)
+            .into_iter()
+            .collect()
+    }
+
+    /// Gets candle start dates between specified dates in ascending order
+    pub fn get_start_dates_ordered(
+        &self,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> Vec<DateTime<Utc>> {
+        self.iter_start_dates(datetime_from, datetime_to).collect()
+    }
+
+    /// Iterates candle start dates between specified dates in ascending order.
+    /// Stops if a date doesn't advance, so misaligned types can't loop forever
+    pub fn iter_start_dates(
+        &self,
+        datetime_from: DateTime<Utc>,
+        datetime_to: DateTime<Utc>,
+    ) -> impl Iterator<Item = DateTime<Utc>> + '_ {
         let date_from = self.get_start_date(datetime_from);
-        dates.insert(date_from);
         let date_to = self.get_start_date(datetime_to);
 
-        let mut last_date = self.get_start_date(date_from);
+        std::iter::successors(Some(date_from), move |last_date| {
+            if *last_date >= date_to {
+                return None;
+            }
 
-        while last_date < date_to {
-            let next_date = self.get_start_date(last_date) + self.get_duration(last_date);
-            last_date = self.get_start_date(next_date);
-            dates.insert(last_date);
-        }
+            let next_date = self.get_start_date(*last_date + self.get_duration(*last_date));
 
-        dates
+            (next_date > *last_date).then_some(next_date)
+        })
     }
 
 
@@ -127,6 +207,31 @@ impl CandleType {
         start + duration
     }
 
+    /// Gets candle start date with Day and Month boundaries at local midnight of the offset,
+    /// e.g. +02:00 starts days at 22:00 UTC. Other candle types ignore the offset
+    pub fn get_start_date_at_offset(&self, datetime: DateTime<Utc>, offset: FixedOffset) -> DateTime<Utc> {
+        match self {
+            CandleType::Day | CandleType::Month => {
+                let offset = Duration::seconds(offset.local_minus_utc() as i64);
+
+                self.get_start_date(datetime + offset) - offset
+            }
+            _ => self.get_start_date(datetime),
+        }
+    }
+
+    /// Gets duration of the candle containing specified date with boundaries of `get_start_date_at_offset`
+    pub fn get_duration_at_offset(&self, datetime: DateTime<Utc>, offset: FixedOffset) -> Duration {
+        match self {
+            CandleType::Month => self.get_duration(datetime + Duration::seconds(offset.local_minus_utc() as i64)),
+            _ => self.get_duration(datetime),
+        }
+    }
+
+    pub fn get_end_date_at_offset(&self, datetime: DateTime<Utc>, offset: FixedOffset) -> DateTime<Utc> {
+        self.get_start_date_at_offset(datetime, offset) + self.get_duration_at_offset(datetime, offset)
+    }
+
     pub fn get_dates_count(&self, datetime_from: DateTime<Utc>, datetime_to: DateTime<Utc>) -> usize {
         let from = self.get_start_date(datetime_from);
         let to = self.get_end_date(datetime_to);
@@ -134,8 +239,8 @@ impl CandleType {
         match self {
             CandleType::Month =>  {
                 let year_diff = to.year() - from.year();
-                let month_diff = to.month() - from.month();
-                let total_month_diff = year_diff * 12 + month_diff as i32;
+                let month_diff = to.month() as i32 - from.month() as i32;
+                let total_month_diff = year_diff * 12 + month_diff;
 
                 total_month_diff as usize
             },
@@ -156,15 +261,36 @@ impl CandleType {
         }
     }
 
+    /// Gets how many candles `datetime` is past `range_start`, both aligned. Dates before `range_start` give 0
+    pub fn index_of(&self, range_start: DateTime<Utc>, datetime: DateTime<Utc>) -> usize {
+        let from = self.get_start_date(range_start);
+        let to = self.get_start_date(datetime);
+
+        if to <= from {
+            return 0;
+        }
+
+        match self {
+            CandleType::Month => {
+                let year_diff = to.year() - from.year();
+                let month_diff = to.month() as i32 - from.month() as i32;
+
+                (year_diff * 12 + month_diff) as usize
+            }
+            _ => {
+                let duration = self.get_duration(from);
+
+                ((to - from).num_seconds() / duration.num_seconds()) as usize
+            }
+        }
+    }
+
     pub fn get_duration(&self, datetime: DateTime<Utc>) -> Duration {
-        let duration = match self {
+        match self {
             CandleType::Minute => Duration::seconds(60),
             CandleType::Hour => Duration::seconds(3600),
             CandleType::Day => Duration::seconds(86400),
             CandleType::Month => {
-                let start_of_month: DateTime<Utc> = Utc
-                    .with_ymd_and_hms(datetime.year(), datetime.month(), 1, 0, 0, 0)
-                    .unwrap();
                 let next_month = if datetime.month() == 12 {
                     1
                 } else {
@@ -177,11 +303,13 @@ impl CandleType {
                     datetime.year()
                 };
 
-                let end_of_month: DateTime<Utc> = Utc
-                    .with_ymd_and_hms(next_year, next_month, 1, 0, 0, 0)
-                    .unwrap();
+                let start_of_month = get_month_start(datetime.year(), datetime.month());
+                let end_of_month = get_month_start(next_year, next_month);
 
-                end_of_month - start_of_month
+                match (start_of_month, end_of_month) {
+                    (Some(start_of_month), Some(end_of_month)) => end_of_month - start_of_month,
+                    _ => Duration::days(30),
+                }
             }
             CandleType::ThreeMinutes => Duration::minutes(3),
             CandleType::FiveMinutes => Duration::minutes(5),
@@ -194,18 +322,101 @@ impl CandleType {
             CandleType::TwelveHours => Duration::hours(12),
             CandleType::ThreeDays => Duration::days(3),
             CandleType::SevenDays => Duration::days(7),
+            CandleType::Session => Duration::days(1),
+            CandleType::Week => Duration::days(7),
+        }
+    }
+}
+
+/// Canonical short form: 1m, 3m, ..., 1h, ..., 1d, 3d, 7d, 1w, 1M and `session`
+impl fmt::Display for CandleType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            CandleType::Minute => "1m",
+            CandleType::ThreeMinutes => "3m",
+            CandleType::FiveMinutes => "5m",
+            CandleType::FifteenMinutes => "15m",
+            CandleType::ThirtyMinutes => "30m",
+            CandleType::Hour => "1h",
+            CandleType::TwoHours => "2h",
+            CandleType::FourHours => "4h",
+            CandleType::SixHours => "6h",
+            CandleType::EightHours => "8h",
+            CandleType::TwelveHours => "12h",
+            CandleType::Day => "1d",
+            CandleType::ThreeDays => "3d",
+            CandleType::SevenDays => "7d",
+            CandleType::Week => "1w",
+            CandleType::Month => "1M",
+            CandleType::Session => "session",
         };
 
-        duration
+        f.write_str(text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCandleTypeError(pub String);
+
+impl fmt::Display for ParseCandleTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unknown candle type {:?}", self.0)
     }
 }
 
+impl std::error::Error for ParseCandleTypeError {}
+
+/// Parses word form (minute, hour, day, week, month, session) or short form produced by `Display`,
+/// ignoring case. The only exception is `1M`: uppercase M is month, while `1m` is minute
+impl FromStr for CandleType {
+    type Err = ParseCandleTypeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let text = s.trim();
+
+        if text == "1M" {
+            return Ok(CandleType::Month);
+        }
+
+        let candle_type = match text.to_ascii_lowercase().as_str() {
+            "minute" | "1m" => CandleType::Minute,
+            "3m" => CandleType::ThreeMinutes,
+            "5m" => CandleType::FiveMinutes,
+            "15m" => CandleType::FifteenMinutes,
+            "30m" => CandleType::ThirtyMinutes,
+            "hour" | "1h" => CandleType::Hour,
+            "2h" => CandleType::TwoHours,
+            "4h" => CandleType::FourHours,
+            "6h" => CandleType::SixHours,
+            "8h" => CandleType::EightHours,
+            "12h" => CandleType::TwelveHours,
+            "day" | "1d" => CandleType::Day,
+            "3d" => CandleType::ThreeDays,
+            "7d" => CandleType::SevenDays,
+            "week" | "1w" => CandleType::Week,
+            "month" => CandleType::Month,
+            "session" => CandleType::Session,
+            _ => return Err(ParseCandleTypeError(s.to_string())),
+        };
+
+        Ok(candle_type)
+    }
+}
+
+/// 1970-01-05 00:00 UTC, the first Monday after Unix epoch (Thursday)
+const FIRST_MONDAY_TIMESTAMP: i64 = 345600;
+
+/// Gets first moment of the month, None if it doesn't map to a single UTC time
+fn get_month_start(year: i32, month: u32) -> Option<DateTime<Utc>> {
+    Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
-    use crate::models::candle_type::CandleType;
-    use chrono::{DateTime, Datelike, Duration, TimeZone, Timelike, Utc};
+    use crate::models::candle_type::{CandleType, ParseCandleTypeError};
+    use chrono::{DateTime, Datelike, Duration, FixedOffset, TimeZone, Timelike, Utc};
 
     #[tokio::test]
     async fn count_minute() {
@@ -245,7 +456,6 @@ mod tests {
 
     
     #[tokio::test]
-    #[ignore]
     async fn count_month() {
         let candle_type = CandleType::Month;
         let num_months = 12;
@@ -257,6 +467,40 @@ mod tests {
         assert_eq!(count, num_months as usize);
     }
 
+    #[tokio::test]
+    async fn count_month_with_different_lengths() {
+        let candle_type = CandleType::Month;
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 12, 6, 0, 0, 0).unwrap();
+
+        assert_eq!(candle_type.get_dates_count(from, to), 12);
+    }
+
+    #[tokio::test]
+    async fn count_month_leap_february() {
+        let candle_type = CandleType::Month;
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(candle_type.get_dates_count(from, Utc.with_ymd_and_hms(2024, 2, 29, 23, 59, 59).unwrap()), 1);
+        assert_eq!(candle_type.get_dates_count(from, Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()), 2);
+        assert_eq!(
+            candle_type.get_dates_count(Utc.with_ymd_and_hms(2024, 1, 31, 0, 0, 0).unwrap(), from),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn count_month_multi_year() {
+        let candle_type = CandleType::Month;
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2022, 11, 15, 0, 0, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2025, 2, 3, 0, 0, 0).unwrap();
+
+        let count = candle_type.get_dates_count(from, to);
+
+        assert_eq!(count, 2 + 12 + 12 + 2);
+        assert_eq!(count, candle_type.get_start_dates_ordered(from, to).len());
+    }
+
     #[tokio::test]
     async fn get_date_for_minute() {
         let candle_type = CandleType::Minute;
@@ -412,4 +656,240 @@ mod tests {
             assert!(dates.contains(&date));
         }
     }
+
+    #[tokio::test]
+    async fn get_start_dates_ordered_for_hour() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 30, 0).unwrap();
+        let to: DateTime<Utc> = from + Duration::hours(15);
+        let candle_type = CandleType::Hour;
+
+        let dates = candle_type.get_start_dates_ordered(from, to);
+        let mut sorted_set: Vec<DateTime<Utc>> = candle_type.get_start_dates(from, to).into_iter().collect();
+        sorted_set.sort();
+
+        assert!(dates.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(dates, sorted_set);
+    }
+
+    #[tokio::test]
+    async fn get_start_dates_ordered_for_month() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(1999, 11, 15, 10, 0, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 3, 20, 0, 0, 0).unwrap();
+        let candle_type = CandleType::Month;
+
+        let dates = candle_type.get_start_dates_ordered(from, to);
+        let months: Vec<(i32, u32)> = dates.iter().map(|date| (date.year(), date.month())).collect();
+
+        assert!(dates.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(dates.first(), Some(&candle_type.get_start_date(from)));
+        assert_eq!(dates.last(), Some(&candle_type.get_start_date(to)));
+        assert_eq!(dates.len(), candle_type.get_dates_count(from, to));
+        assert_eq!(months, vec![(1999, 11), (1999, 12), (2000, 1), (2000, 2), (2000, 3)]);
+        assert!(dates.iter().all(|date| date.day() == 1 && date.hour() == 0));
+    }
+
+    #[tokio::test]
+    async fn protocol_code_round_trip() {
+        let codes = ["M1", "M3", "M5", "M15", "M30", "H1", "H2", "H4", "H6", "H8", "H12", "D1", "W1", "MN"];
+
+        for code in codes {
+            let candle_type = CandleType::from_protocol_code(code).unwrap();
+            assert_eq!(candle_type.to_protocol_code(), Some(code));
+        }
+
+        assert_eq!(CandleType::from_protocol_code("MN1"), Some(CandleType::Month));
+        assert_eq!(CandleType::from_protocol_code("X1"), None);
+        assert_eq!(CandleType::ThreeDays.to_protocol_code(), None);
+        assert_eq!(CandleType::SevenDays.to_protocol_code(), None);
+    }
+
+    #[tokio::test]
+    async fn month_december_to_january_rollover() {
+        let candle_type = CandleType::Month;
+        let src_date: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap();
+
+        let start_date = candle_type.get_start_date(src_date);
+        let end_date = candle_type.get_end_date(src_date);
+        let dates = candle_type.get_start_dates_ordered(src_date, src_date + Duration::days(1));
+
+        assert_eq!(start_date, Utc.with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap());
+        assert_eq!(candle_type.get_duration(src_date), Duration::days(31));
+        assert_eq!(end_date, Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(dates, vec![start_date, end_date]);
+    }
+
+    #[tokio::test]
+    async fn index_of_minute() {
+        let candle_type = CandleType::Minute;
+        let range_start: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 30).unwrap();
+
+        assert_eq!(candle_type.index_of(range_start, range_start), 0);
+        assert_eq!(candle_type.index_of(range_start, range_start + Duration::seconds(45)), 1);
+        assert_eq!(candle_type.index_of(range_start, range_start + Duration::minutes(90)), 90);
+        assert_eq!(candle_type.index_of(range_start, range_start - Duration::minutes(5)), 0);
+    }
+
+    #[tokio::test]
+    async fn index_of_month() {
+        let candle_type = CandleType::Month;
+        let range_start: DateTime<Utc> = Utc.with_ymd_and_hms(2023, 11, 15, 0, 0, 0).unwrap();
+
+        assert_eq!(candle_type.index_of(range_start, Utc.with_ymd_and_hms(2023, 11, 30, 0, 0, 0).unwrap()), 0);
+        assert_eq!(candle_type.index_of(range_start, Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap()), 4);
+        assert_eq!(candle_type.index_of(range_start, Utc.with_ymd_and_hms(2025, 2, 28, 0, 0, 0).unwrap()), 15);
+    }
+
+    #[tokio::test]
+    async fn three_days_cross_midnight() {
+        let candle_type = CandleType::ThreeDays;
+        let midnight: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 2, 0, 0, 0).unwrap();
+        let from = midnight - Duration::days(4);
+        let to = midnight + Duration::days(4);
+
+        let dates = candle_type.get_start_dates_ordered(from, to);
+
+        assert!(dates.iter().all(|date| date.timestamp() % 259200 == 0));
+        assert!(dates.windows(2).all(|pair| pair[1] - pair[0] == Duration::days(3)));
+        assert!(dates[0] <= from && *dates.last().unwrap() <= to && to < candle_type.get_end_date(to));
+        assert_eq!(candle_type.get_dates_count(from, to), dates.len());
+
+        let bucket = candle_type.get_start_date(midnight);
+        assert!(bucket <= midnight && midnight < candle_type.get_end_date(midnight));
+        assert_eq!(CandleType::SevenDays.get_start_date(midnight).timestamp() % 604800, 0);
+    }
+
+    #[tokio::test]
+    async fn start_dates_ordered_for_every_type() {
+        let from: DateTime<Utc> = Utc.with_ymd_and_hms(1999, 12, 20, 13, 17, 0).unwrap();
+        let to: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 3, 5, 8, 0, 0).unwrap();
+        let candle_types: Vec<CandleType> = (0..).map_while(|value| CandleType::try_from(value).ok()).collect();
+
+        assert_eq!(candle_types.len(), 17);
+
+        for candle_type in candle_types {
+            let dates = candle_type.get_start_dates_ordered(from, to);
+
+            assert_eq!(dates.first(), Some(&candle_type.get_start_date(from)), "{:?}", candle_type);
+            assert_eq!(dates.last(), Some(&candle_type.get_start_date(to)), "{:?}", candle_type);
+            assert!(dates.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", candle_type);
+            assert!(
+                dates.iter().all(|date| candle_type.get_start_date(*date) == *date),
+                "{:?}",
+                candle_type
+            );
+            assert_eq!(dates.len(), candle_type.get_dates_count(from, to), "{:?}", candle_type);
+        }
+    }
+
+    #[tokio::test]
+    async fn four_hours_and_week_alignment() {
+        // 2000-01-05 is Wednesday
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 5, 13, 45, 0).unwrap();
+
+        assert_eq!(
+            CandleType::FourHours.get_start_date(date),
+            Utc.with_ymd_and_hms(2000, 1, 5, 12, 0, 0).unwrap()
+        );
+
+        let week_start = CandleType::Week.get_start_date(date);
+
+        assert_eq!(week_start, Utc.with_ymd_and_hms(2000, 1, 3, 0, 0, 0).unwrap());
+        assert_eq!(week_start.weekday(), chrono::Weekday::Mon);
+        assert_eq!(CandleType::Week.get_start_date(week_start), week_start);
+        assert_eq!(
+            CandleType::Week.get_start_date(week_start - Duration::seconds(1)),
+            week_start - Duration::days(7)
+        );
+        assert_eq!(CandleType::Week.get_dates_count(week_start, week_start + Duration::days(20)), 3);
+        assert_eq!(CandleType::FourHours.get_dates_count(week_start, week_start + Duration::hours(23)), 6);
+    }
+
+    #[tokio::test]
+    async fn five_and_fifteen_minutes_across_hour() {
+        let hour = Utc.with_ymd_and_hms(2000, 1, 1, 11, 0, 0).unwrap();
+        let from = hour - Duration::minutes(17);
+        let to = hour + Duration::minutes(17);
+
+        for (candle_type, minutes) in [(CandleType::FiveMinutes, 5), (CandleType::FifteenMinutes, 15)] {
+            let step = Duration::minutes(minutes);
+
+            assert_eq!(candle_type.get_start_date(hour - Duration::seconds(1)), hour - step);
+            assert_eq!(candle_type.get_start_date(hour), hour);
+            assert_eq!(candle_type.get_start_date(hour + step - Duration::seconds(1)), hour);
+            assert_eq!(candle_type.get_duration(hour), step);
+
+            let dates = candle_type.get_start_dates_ordered(from, to);
+
+            assert_eq!(dates.len(), candle_type.get_dates_count(from, to));
+            assert!(dates.contains(&hour));
+            assert!(dates.iter().all(|date| date.timestamp() % (minutes * 60) == 0));
+        }
+
+        assert_eq!(CandleType::FiveMinutes.get_dates_count(from, to), 8);
+        assert_eq!(CandleType::FifteenMinutes.get_dates_count(from, to), 4);
+    }
+
+    #[tokio::test]
+    async fn day_and_month_at_offset() {
+        let offset = FixedOffset::east_opt(2 * 3600).unwrap();
+        let date = Utc.with_ymd_and_hms(2000, 1, 31, 23, 0, 0).unwrap();
+        let day_start = Utc.with_ymd_and_hms(2000, 1, 31, 22, 0, 0).unwrap();
+
+        assert_eq!(CandleType::Day.get_start_date_at_offset(date, offset), day_start);
+        assert_eq!(CandleType::Day.get_end_date_at_offset(date, offset), day_start + Duration::days(1));
+        assert_eq!(
+            CandleType::Day.get_start_date_at_offset(day_start - Duration::seconds(1), offset),
+            day_start - Duration::days(1)
+        );
+
+        // 23:00 UTC on Jan 31 is already February at +02:00
+        assert_eq!(CandleType::Month.get_start_date_at_offset(date, offset), day_start);
+        assert_eq!(CandleType::Month.get_duration_at_offset(date, offset), Duration::days(29));
+        assert_eq!(
+            CandleType::Month.get_start_date_at_offset(day_start - Duration::seconds(1), offset),
+            Utc.with_ymd_and_hms(1999, 12, 31, 22, 0, 0).unwrap()
+        );
+
+        let utc = FixedOffset::east_opt(0).unwrap();
+
+        assert_eq!(CandleType::Day.get_start_date_at_offset(date, utc), CandleType::Day.get_start_date(date));
+        assert_eq!(CandleType::Hour.get_start_date_at_offset(date, offset), CandleType::Hour.get_start_date(date));
+    }
+
+    #[tokio::test]
+    async fn parse_from_str() {
+        let cases = [
+            ("minute", CandleType::Minute),
+            ("MINUTE", CandleType::Minute),
+            ("1m", CandleType::Minute),
+            ("hour", CandleType::Hour),
+            ("1H", CandleType::Hour),
+            (" Day ", CandleType::Day),
+            ("1d", CandleType::Day),
+            ("Month", CandleType::Month),
+            ("1M", CandleType::Month),
+            ("4h", CandleType::FourHours),
+            ("1W", CandleType::Week),
+        ];
+
+        for (text, candle_type) in cases {
+            assert_eq!(text.parse::<CandleType>(), Ok(candle_type), "{}", text);
+        }
+
+        for text in ["", "garbage", "2m", "1y", "minutes", "1 m"] {
+            assert_eq!(text.parse::<CandleType>(), Err(ParseCandleTypeError(text.to_string())));
+        }
+    }
+
+    #[tokio::test]
+    async fn display_round_trip() {
+        for value in 0..17 {
+            let candle_type = CandleType::try_from(value).unwrap();
+
+            assert_eq!(candle_type.to_string().parse::<CandleType>(), Ok(candle_type));
+        }
+
+        assert_eq!(CandleType::Month.to_string(), "1M");
+        assert_eq!(CandleType::Minute.to_string(), "1m");
+    }
 }
@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+
+use super::{candle_type::CandleType, side::Side};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistencyIssue {
+    /// Coarse candle high is below the high of a candle inside it
+    HighBelowInner,
+    /// Coarse candle low is above the low of a candle inside it
+    LowAboveInner,
+}
+
+/// Discrepancy between a coarse candle and a finer candle inside its interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsistencyError {
+    pub instrument: String,
+    pub side: Side,
+    pub candle_type: CandleType,
+    pub datetime: DateTime<Utc>,
+    pub inner_candle_type: CandleType,
+    pub inner_datetime: DateTime<Utc>,
+    pub issue: ConsistencyIssue,
+}
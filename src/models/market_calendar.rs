@@ -0,0 +1,48 @@
+use chrono::{DateTime, Datelike, Utc, Weekday};
+use crate::models::trading_session::TradingSession;
+
+/// Regular trading hours of a market: the session repeated on trading days, anything else
+/// is extended hours or closed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketCalendar {
+    pub regular_hours: TradingSession,
+    /// Weekdays of regular session starts
+    pub trading_days: Vec<Weekday>,
+}
+
+impl MarketCalendar {
+    pub fn new(regular_hours: TradingSession, trading_days: Vec<Weekday>) -> Self {
+        Self { regular_hours, trading_days }
+    }
+
+    pub fn is_regular_hours(&self, datetime: DateTime<Utc>) -> bool {
+        match self.regular_hours.get_start_date(datetime) {
+            Some(session_start) => self.trading_days.contains(&session_start.weekday()),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{market_calendar::MarketCalendar, trading_session::TradingSession};
+    use chrono::{NaiveTime, TimeZone, Utc, Weekday};
+
+    #[tokio::test]
+    async fn is_regular_hours() {
+        let calendar = MarketCalendar::new(
+            TradingSession {
+                name: "NYSE".to_string(),
+                start: NaiveTime::from_hms_opt(14, 30, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(21, 0, 0).unwrap(),
+            },
+            vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri],
+        );
+
+        // 2000-01-03 is Monday
+        assert!(calendar.is_regular_hours(Utc.with_ymd_and_hms(2000, 1, 3, 15, 0, 0).unwrap()));
+        assert!(!calendar.is_regular_hours(Utc.with_ymd_and_hms(2000, 1, 3, 14, 0, 0).unwrap()));
+        assert!(!calendar.is_regular_hours(Utc.with_ymd_and_hms(2000, 1, 3, 21, 0, 0).unwrap()));
+        assert!(!calendar.is_regular_hours(Utc.with_ymd_and_hms(2000, 1, 2, 15, 0, 0).unwrap()));
+    }
+}
@@ -1,4 +1,13 @@
 pub mod candle_type;
+pub mod candle_interval;
 pub mod candle_data;
+pub mod candle_data_formats;
 pub mod candle;
-pub mod candle_pager;
\ No newline at end of file
+pub mod candle_pager;
+pub mod side;
+pub mod candle_stats;
+#[cfg(feature = "prost")]
+pub mod candle_proto;
+pub mod trading_session;
+pub mod consistency_error;
+pub mod market_calendar;
\ No newline at end of file
@@ -0,0 +1,14 @@
+use super::candle_data::CandleData;
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+/// Candle data labeled with the side it belongs to
+#[derive(Debug, Clone)]
+pub struct SidedCandle {
+    pub side: Side,
+    pub data: CandleData,
+}
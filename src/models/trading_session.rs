@@ -0,0 +1,122 @@
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+
+/// Intraday session window in UTC. Session crosses midnight when `end` isn't after `start`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradingSession {
+    pub name: String,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TradingSession {
+    pub fn get_duration(&self) -> Duration {
+        if self.end > self.start {
+            self.end - self.start
+        } else {
+            self.end - self.start + Duration::days(1)
+        }
+    }
+
+    /// Gets start of the session containing specified date, None if it's outside the session
+    pub fn get_start_date(&self, datetime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let date = datetime.date_naive();
+        let time = datetime.time();
+
+        if self.end > self.start {
+            if time >= self.start && time < self.end {
+                return Some(date.and_time(self.start).and_utc());
+            }
+        } else if time >= self.start {
+            return Some(date.and_time(self.start).and_utc());
+        } else if time < self.end {
+            return Some((date - Duration::days(1)).and_time(self.start).and_utc());
+        }
+
+        None
+    }
+}
+
+/// Set of trading sessions used to align `CandleType::Session` candles.
+/// If sessions overlap, the first one containing a date wins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradingSessions {
+    pub sessions: Vec<TradingSession>,
+}
+
+impl TradingSessions {
+    pub fn new(sessions: Vec<TradingSession>) -> Self {
+        Self { sessions }
+    }
+
+    pub fn get_session(&self, datetime: DateTime<Utc>) -> Option<&TradingSession> {
+        self.sessions
+            .iter()
+            .find(|session| session.get_start_date(datetime).is_some())
+    }
+
+    /// Gets start of the session containing specified date, None for closed periods
+    pub fn get_start_date(&self, datetime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.sessions
+            .iter()
+            .find_map(|session| session.get_start_date(datetime))
+    }
+
+    /// Gets the earliest session start not before specified date, None if there are no sessions
+    pub fn get_next_start_date(&self, datetime: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let date = datetime.date_naive();
+
+        self.sessions
+            .iter()
+            .flat_map(|session| [date, date + Duration::days(1)].map(|day| day.and_time(session.start).and_utc()))
+            .filter(|start| *start >= datetime)
+            .min()
+    }
+
+    /// Gets length of the session containing specified date, None for closed periods
+    pub fn get_duration(&self, datetime: DateTime<Utc>) -> Option<Duration> {
+        self.get_session(datetime).map(|session| session.get_duration())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::trading_session::{TradingSession, TradingSessions};
+    use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+
+    #[tokio::test]
+    async fn session_crossing_midnight() {
+        let sessions = TradingSessions::new(vec![TradingSession {
+            name: "Asia".to_string(),
+            start: NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+        }]);
+        let session_start: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 23, 0, 0).unwrap();
+
+        assert_eq!(sessions.get_start_date(session_start + Duration::minutes(30)), Some(session_start));
+        assert_eq!(sessions.get_start_date(session_start + Duration::hours(5)), Some(session_start));
+        assert_eq!(sessions.get_start_date(session_start + Duration::hours(10)), None);
+        assert_eq!(sessions.get_duration(session_start), Some(Duration::hours(9)));
+    }
+
+    #[tokio::test]
+    async fn next_start_date() {
+        let sessions = TradingSessions::new(vec![
+            TradingSession {
+                name: "London".to_string(),
+                start: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+            },
+            TradingSession {
+                name: "Asia".to_string(),
+                start: NaiveTime::from_hms_opt(23, 0, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(8, 0, 0).unwrap(),
+            },
+        ]);
+        let date: DateTime<Utc> = Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(sessions.get_next_start_date(date + Duration::hours(8)), Some(date + Duration::hours(8)));
+        assert_eq!(sessions.get_next_start_date(date + Duration::hours(16)), Some(date + Duration::hours(23)));
+        assert_eq!(sessions.get_next_start_date(date + Duration::hours(23) + Duration::seconds(1)), Some(date + Duration::hours(32)));
+        assert_eq!(TradingSessions::new(vec![]).get_next_start_date(date), None);
+    }
+}
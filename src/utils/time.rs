@@ -0,0 +1,51 @@
+use std::fmt;
+
+use chrono::{DateTime, TimeZone, Utc};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TimeError {
+    MillisOutOfRange(i64),
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeError::MillisOutOfRange(millis) => write!(f, "Timestamp millis {} is out of range", millis),
+        }
+    }
+}
+
+impl std::error::Error for TimeError {}
+
+/// Converts epoch millis to date. Negative millis are dates before 1970
+pub fn datetime_from_millis(millis: i64) -> Result<DateTime<Utc>, TimeError> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .ok_or(TimeError::MillisOutOfRange(millis))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::time::{datetime_from_millis, TimeError};
+    use chrono::{TimeZone, Utc};
+
+    #[tokio::test]
+    async fn valid_millis() {
+        let date = datetime_from_millis(946684800123).unwrap();
+
+        assert_eq!(date, Utc.timestamp_millis_opt(946684800123).unwrap());
+    }
+
+    #[tokio::test]
+    async fn negative_millis() {
+        let date = datetime_from_millis(-86400000).unwrap();
+
+        assert_eq!(date, Utc.with_ymd_and_hms(1969, 12, 31, 0, 0, 0).unwrap());
+    }
+
+    #[tokio::test]
+    async fn overflowing_millis() {
+        assert_eq!(datetime_from_millis(i64::MAX), Err(TimeError::MillisOutOfRange(i64::MAX)));
+        assert_eq!(datetime_from_millis(i64::MIN), Err(TimeError::MillisOutOfRange(i64::MIN)));
+    }
+}
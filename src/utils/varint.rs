@@ -0,0 +1,69 @@
+/// Writes LEB128 varint
+pub fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+/// Reads LEB128 varint moving `position` past it, None if bytes end or varint is too long
+pub fn read_varint(bytes: &[u8], position: &mut usize) -> Option<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*position)?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+/// Maps signed value to unsigned so that small negative values stay small
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::varint::{read_varint, write_varint, zigzag_decode, zigzag_encode};
+
+    #[tokio::test]
+    async fn varint_round_trip() {
+        let values = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+        let mut bytes = Vec::new();
+
+        for value in values {
+            write_varint(&mut bytes, value);
+        }
+
+        let mut position = 0;
+
+        for value in values {
+            assert_eq!(read_varint(&bytes, &mut position), Some(value));
+        }
+
+        assert_eq!(read_varint(&bytes, &mut position), None);
+        assert_eq!(bytes[..2], [0, 1]);
+    }
+
+    #[tokio::test]
+    async fn zigzag_round_trip() {
+        for value in [0, -1, 1, -64, 64, i64::MIN, i64::MAX] {
+            assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+        }
+
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+    }
+}